@@ -6,11 +6,13 @@ use clap::{Parser, ValueEnum};
 pub enum CompileTarget {
     /// Default Lua target, 5.3
     Lua,
+    /// Lua 5.4, cleans up deferred code with `<close>` variables.
+    Lua54,
 }
 impl CompileTarget {
     pub fn ext(&self) -> String {
         match self {
-            Self::Lua => "lua",
+            Self::Lua | Self::Lua54 => "lua",
         }
         .into()
     }
@@ -31,7 +33,7 @@ pub enum Args {
         /// The input file to compile.
         #[arg(long, short)]
         input: Option<PathBuf>,
-        /// Only processes the macro code and expands it, currently disabled (ignored).
+        /// Only expands the macros, the output is the expanded Saturnus code.
         #[arg(long)]
         only_macros: bool,
         /// The module resolution strategy to use.
@@ -52,6 +54,10 @@ pub enum Args {
         /// Unit will be treated as it's own object, instead of translating to platform-null value.
         #[arg(long)]
         disable_unit_interop: bool,
+        /// Returns tuples as multiple values instead of tuple tables. Only tuple patterns
+        /// receive all of them: `let t = f();` keeps the first value alone.
+        #[arg(long)]
+        multiple_returns: bool,
         /// Output file target, if skipped, will match the input plus the output extension.
         #[arg(long, short)]
         output: Option<PathBuf>,
@@ -4,25 +4,31 @@ mod options;
 use std::{
     fs::File,
     io::{Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::exit,
 };
 
 use cli::{Args, CompileTarget};
 use colored::Colorize;
 use options::OptionsAdapter;
-use saturnus::{Saturnus, Table, compiler::CompilerOptions, source::SourceCode};
+use saturnus::{
+    RuntimeError, Saturnus, Table,
+    compiler::{CompilerError, CompilerOptions},
+    parsing::{ast::FixityDecl, parse_program},
+    processing::fixity,
+    source::SourceCode,
+};
 
 fn read_file_as_source(
-    mut input: PathBuf,
+    input: PathBuf,
     override_path: Option<PathBuf>,
 ) -> Result<impl SourceCode, std::io::Error> {
     let mut source = String::new();
     File::open(&input)?.read_to_string(&mut source)?;
-    input.set_extension("");
     struct Src {
         source: String,
         location: PathBuf,
+        file: PathBuf,
     }
     impl SourceCode for Src {
         fn source(self) -> String {
@@ -31,13 +37,50 @@ fn read_file_as_source(
         fn location(&self) -> Option<PathBuf> {
             Some(self.location.clone())
         }
+        fn file(&self) -> Option<PathBuf> {
+            Some(self.file.clone())
+        }
     }
     Ok(Src {
         source,
-        location: override_path.unwrap_or(input),
+        location: override_path.unwrap_or_else(|| input.with_extension("")),
+        file: input,
+    })
+}
+
+/// The directory module paths start from: the working directory, unless the
+/// input is given a module path of its own, which then ends at the input.
+fn module_root(input: &Path, mod_path: Option<&Path>) -> PathBuf {
+    let Some(mod_path) = mod_path else {
+        return PathBuf::new();
+    };
+    let mut root = input.to_path_buf();
+    for _ in mod_path.components() {
+        root.pop();
+    }
+    root
+}
+
+/// Public fixities of the units the source uses, read from their `.st` files.
+fn imported_fixities(source: &str, root: &Path) -> Vec<FixityDecl> {
+    let (program, _) = parse_program(source);
+    fixity::imported(&program, |path| {
+        let mut file = root.join(path.join("/"));
+        file.set_extension("st");
+        std::fs::read_to_string(file).ok()
     })
 }
 
+/// Options for compiling the input file, with the fixities it imports.
+fn file_options(input: &Path, options: &CompilerOptions) -> CompilerOptions {
+    let mut options = options.clone();
+    if let Ok(source) = std::fs::read_to_string(input) {
+        let root = module_root(input, options.override_mod_path.as_deref());
+        options.fixities = imported_fixities(&source, &root);
+    }
+    options
+}
+
 enum OutputVariant {
     Stdout,
     File(PathBuf),
@@ -59,17 +102,41 @@ impl OutputVariant {
     }
 }
 
+/// Compiles the source, or only expands its macros.
+fn process(
+    c: &Saturnus,
+    source: impl SourceCode,
+    target: CompileTarget,
+    only_macros: bool,
+) -> Result<String, ()> {
+    if only_macros {
+        return c.expand_macros(source).report_errors();
+    }
+    match target {
+        CompileTarget::Lua | CompileTarget::Lua54 => {
+            c.compile(source).report_errors().map(|ir| ir.to_string())
+        }
+    }
+}
+
 fn compile_file(
     input: PathBuf,
     options: CompilerOptions,
     target: CompileTarget,
     output: OutputVariant,
+    only_macros: bool,
 ) {
     let output = match output {
         OutputVariant::File(output) => output,
         OutputVariant::Stdout => {
             todo!()
         }
+        // Expanded code is Saturnus too, so it can't take the input's place.
+        OutputVariant::Nothing if only_macros => {
+            let mut input = input.clone();
+            input.set_extension("expanded.st");
+            input
+        }
         OutputVariant::Nothing => {
             let mut input = input.clone();
             input.set_extension(target.ext());
@@ -83,12 +150,12 @@ fn compile_file(
         }
     };
     let mut c = Saturnus::new();
-    c.options = options.clone();
-    let out = match target {
-        CompileTarget::Lua => c.compile(source).unwrap(),
+    c.options = file_options(&input, &options);
+    let Ok(out) = process(&c, source, target, only_macros) else {
+        exit(1);
     };
     let mut out_file = File::create(&output).unwrap();
-    write!(out_file, "{}", out.to_string()).unwrap();
+    write!(out_file, "{}", out).unwrap();
 }
 
 fn produce_std(output: PathBuf) {
@@ -98,18 +165,31 @@ fn produce_std(output: PathBuf) {
     write!(out_file, "{}", out.to_string()).unwrap();
 }
 
+/// Compilation failures are wrapped by the runtime, but what the user wants to
+/// see are the diagnostics of the underlying compiler error.
+fn describe_error(err: &(dyn std::error::Error + 'static)) -> String {
+    let compiler_error = err
+        .downcast_ref::<RuntimeError>()
+        .and_then(|err| err.caused_by.as_ref())
+        .and_then(|cause| cause.downcast_ref::<CompilerError>());
+    match compiler_error {
+        Some(cause) => cause.to_string(),
+        None => err.to_string(),
+    }
+}
+
 trait ErrorReporter<T> {
     fn report_errors(self) -> Result<T, ()>;
 }
 impl<T, E> ErrorReporter<T> for Result<T, E>
 where
-    E: std::fmt::Debug,
+    E: std::error::Error + 'static,
 {
     fn report_errors(self) -> Result<T, ()> {
         match self {
             Ok(ok) => Ok(ok),
             Err(err) => {
-                eprintln!("{}", format!("{err:?}").red());
+                eprintln!("{}", describe_error(&err).red());
                 Err(())
             }
         }
@@ -124,9 +204,10 @@ async fn run(
 ) -> Result<(), ()> {
     let source_loc = input.clone().to_str().unwrap_or("").to_owned();
     // TODO ^^^^^^ Handle this.
-    let source = read_file_as_source(input, options.override_mod_path.clone()).report_errors()?;
+    let source =
+        read_file_as_source(input.clone(), options.override_mod_path.clone()).report_errors()?;
     let mut sat = Saturnus::new();
-    sat.options = options.clone();
+    sat.options = file_options(&input, &options);
     let ir = sat.compile(source).report_errors()?;
     if dump_ir {
         println!("{}\n", format!("{}", ir.to_string()).dimmed());
@@ -160,7 +241,11 @@ async fn run(
         eprintln!("WARNING: Native library loading is not implemented.");
     }
     globals.set("__modules__", __modules__).unwrap();
-    sat.load_ir(ir).report_errors()?.exec().report_errors()?;
+    sat.load_ir(ir)
+        .report_errors()?
+        .exec_async()
+        .await
+        .report_errors()?;
     Ok(())
 }
 
@@ -169,19 +254,20 @@ fn compile_input(
     output: OutputVariant,
     options: CompilerOptions,
     target: CompileTarget,
+    only_macros: bool,
 ) {
     let mut c = Saturnus::new();
     c.options = options.clone();
-    let out = match target {
-        CompileTarget::Lua => c.compile(input).unwrap(),
+    let Ok(out) = process(&c, input, target, only_macros) else {
+        exit(1);
     };
     match output {
         OutputVariant::Stdout => {
-            println!("{}", out.to_string());
+            println!("{}", out);
         }
         OutputVariant::File(output) => {
             let mut out_file = File::create(&output).unwrap();
-            write!(out_file, "{}", out.to_string()).unwrap();
+            write!(out_file, "{}", out).unwrap();
         }
         OutputVariant::Nothing => todo!(),
     }
@@ -201,6 +287,7 @@ async fn main() {
             code,
             stdout,
             mod_path,
+            only_macros,
             ..
         } => {
             let output = OutputVariant::from_args(stdout, output);
@@ -215,9 +302,17 @@ async fn main() {
                     }
                 }
                 println!("code := {code:?}");
-                compile_input(RawCode(code, mod_path), output, options, target);
+                let mut options = options;
+                options.fixities = imported_fixities(&code, Path::new(""));
+                compile_input(
+                    RawCode(code, mod_path),
+                    output,
+                    options,
+                    target,
+                    only_macros,
+                );
             } else if let Some(input) = input {
-                compile_file(input, options, target, output);
+                compile_file(input, options, target, output, only_macros);
             } else {
                 eprintln!("Specify either --input or --code!")
             }
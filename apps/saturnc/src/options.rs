@@ -1,6 +1,6 @@
-use saturnus::compiler::{CompilerOptions, ModuleType};
+use saturnus::compiler::{CompilerOptions, LuaVersion, ModuleType};
 
-use crate::cli::Args;
+use crate::cli::{Args, CompileTarget};
 
 pub struct OptionsAdapter;
 
@@ -11,12 +11,13 @@ impl OptionsAdapter {
     pub fn parse_args(&self, args: &Args) -> CompilerOptions {
         match args {
             Args::Compile {
-                only_macros,
                 module_resolution,
                 static_is_global,
                 use_std_collections,
                 disable_loop_interop,
                 disable_unit_interop,
+                multiple_returns,
+                target,
                 mod_path,
                 ..
             } => CompilerOptions {
@@ -24,6 +25,13 @@ impl OptionsAdapter {
                 skip_loop_interop: *disable_loop_interop,
                 unit_interop: !*disable_unit_interop,
                 override_mod_path: mod_path.clone(),
+                // Depend on the input, see `file_options`.
+                fixities: vec![],
+                multiple_returns: *multiple_returns,
+                lua_version: match target {
+                    CompileTarget::Lua => LuaVersion::Lua53,
+                    CompileTarget::Lua54 => LuaVersion::Lua54,
+                },
                 module_type: match module_resolution {
                     crate::cli::ModSys::Saturnus => ModuleType::Saturnus,
                     crate::cli::ModSys::Native => ModuleType::LocalModuleReturn,
@@ -1,27 +1,225 @@
+use std::collections::HashMap;
+
 use lazy_static::lazy_static;
 use regex::Regex;
 
 use crate::{
     code::IndentedBuilder,
-    compiler::{Compiler, CompilerError, CompilerOptions, ModuleType, Result},
+    compiler::{Compiler, CompilerError, CompilerOptions, LuaVersion, ModuleType, Result},
+    diagnostics::Diagnostic,
     parsing::{
         ast::{
-            ArrayAccess, ArrayLiteral, Assignment, AssignmentTarget, Boolean, Bop, Call, ClassDef,
-            ClassField, DefModifiers, Destructure, DestructureEntry, ElseIf, Expr, Fn, For,
-            Identifier, IfStatement, IntoAssignmentTarget, IntoExpr, IntoMapKey, IntoStatement,
-            LambdaExpr, Let, Loop, MapKey, MapLiteral, Member, MemberOp, Number, Operator, Param,
-            Return, SatString, Statement, TupleLiteral, Uop, Use, While,
+            ArrayAccess, ArrayComprehension, ArrayLiteral, Assignment, AssignmentTarget, Await,
+            Block, Boolean, Bop, Break, Call, Catch, ClassDef, ClassField, ComprehensionLoop,
+            Decorator, DefModifiers, Defer, Destructure, DestructureEntry, ElseIf, EnumDef,
+            EnumVariant, Expr, Fn, For, Identifier, IfExpr, IfStatement, InterpolatedString,
+            IntoAssignmentTarget, IntoExpr, IntoMapKey, IntoStatement, LambdaExpr, Let, Loop,
+            MapComprehension, MapKey, MapLiteral, Match, MatchArm, Member, MemberOp, Number,
+            Operator, Param, Pattern, Return, SatString, Skip, Span, Spanned, Spread, Statement,
+            StringFragment, Throw, TraitDef, TraitField, Try, TupleLiteral, Uop, Use, While,
         },
-        builders::{AddArrayAccess, AddMember, LeafCollector},
-        grammar::ProgramParser,
+        builders::{AddArrayAccess, AddMember, AsExpr, LeafCollector},
+        parse_program,
+    },
+    processing::{
+        constants::inline_constants,
+        fixity::reassociate,
+        macros::expand_macros,
+        optional::lower_optionals,
+        visitor::{VisitorMut, walk_expr, walk_statement},
     },
     source::{SaturnusIR, SourceCode},
+    typecheck::check_types,
 };
 
+/// Drives a task (a coroutine) to completion, forwarding its suspensions to
+/// whoever runs the current one, so a pending Rust future suspends the whole
+/// chain up to the script's executor. Other values are already resolved.
+pub(crate) const AWAIT_RUNTIME: &str = r#"__await__ = __await__ or function(task)
+  if type(task) ~= "thread" then
+    return task;
+  end
+  while true do
+    local result = table.pack(coroutine.resume(task));
+    if not result[1] then
+      error(result[2], 0);
+    end
+    if coroutine.status(task) == "dead" then
+      return table.unpack(result, 2, result.n);
+    end
+    coroutine.yield(table.unpack(result, 2, result.n));
+  end
+end;"#;
+
+/// Tells if the value is an instance of the class, or of any of its
+/// subclasses: their metatables are linked to the parent's through
+/// `__parent__`.
+pub(crate) const INSTANCE_OF_RUNTIME: &str = r#"__instance_of__ = __instance_of__ or function(value, class)
+  local meta = getmetatable(value);
+  while meta ~= nil do
+    if meta == class.__meta__ then
+      return true;
+    end
+    meta = rawget(meta, "__parent__");
+  end
+  return false;
+end;"#;
+
+/// Gives back the values a call returned, unless it returned a single tuple,
+/// whose `size` first fields are given back instead: functions may return
+/// tuples held in variables, see `CompilerOptions::multiple_returns`.
+pub(crate) const TUPLE_VALUES_RUNTIME: &str = r##"__tuple_values__ = __tuple_values__ or function(size, ...)
+  local tuple = ...;
+  if select("#", ...) ~= 1 or type(tuple) ~= "table" then
+    return ...;
+  end
+  local values = {};
+  for i = 1, size do
+    values[i] = tuple["__" .. (i - 1)];
+  end
+  return table.unpack(values, 1, size);
+end;"##;
+
+/// Control flow leaving a protected `try` body, see [`LuaCompiler::compile_try`].
+#[derive(Default, Clone)]
+struct Signals {
+    returns: bool,
+    /// In order of appearance, without repetitions.
+    jumps: Vec<Jump>,
+}
+impl Signals {
+    fn send(&mut self, jump: Jump) {
+        if !self.jumps.contains(&jump) {
+            self.jumps.push(jump);
+        }
+    }
+    fn merge(&mut self, other: Signals) {
+        self.returns |= other.returns;
+        for jump in other.jumps {
+            self.send(jump);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Signal {
+    Break,
+    Skip,
+}
+
+/// A `break` or a `skip`, with the label of the loop it targets.
+#[derive(Clone, PartialEq)]
+struct Jump {
+    signal: Signal,
+    label: Option<String>,
+}
+impl Jump {
+    fn keyword(&self) -> &'static str {
+        match self.signal {
+            Signal::Break => "break",
+            Signal::Skip => "skip",
+        }
+    }
+    /// Sent through `pcall`, labels can't contain spaces.
+    fn name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{} {label}", self.keyword()),
+            None => self.keyword().into(),
+        }
+    }
+}
+
+/// Loop being compiled.
+struct LoopFrame {
+    label: Option<String>,
+    /// Suffix of the Lua labels of the loop, unique in the unit.
+    id: usize,
+    /// Whether a nested loop breaks out of this one, which needs a label past
+    /// its end.
+    exits: bool,
+}
+
+/// Function like scope being compiled, as `break` and `skip` can only target
+/// loops within it.
+#[derive(Default)]
+struct Scope {
+    /// Innermost last.
+    loops: Vec<LoopFrame>,
+    /// Set for the functions wrapping protected `try` bodies.
+    protected: Option<Signals>,
+    /// Set for deferred bodies, which run after the scope is left and so
+    /// can't leave it again.
+    deferred: bool,
+}
+
+/// Finds the statements jumping out of an expression: `return`, and `break`
+/// or `skip` outside of the loops of the expression. Nested functions and
+/// lambdas are left alone, as their jumps stay inside.
+#[derive(Default)]
+struct JumpFinder {
+    loops: usize,
+}
+impl VisitorMut for JumpFinder {
+    type Error = (&'static str, Span);
+
+    fn visit_statement(
+        &mut self,
+        statement: &mut Statement,
+    ) -> std::result::Result<(), Self::Error> {
+        match statement {
+            Statement::Return(Return { span, .. }) => Err(("return", *span)),
+            Statement::Break(Break { span, .. }) if self.loops == 0 => Err(("break", *span)),
+            Statement::Skip(Skip { span, .. }) if self.loops == 0 => Err(("skip", *span)),
+            Statement::For(_) | Statement::While(_) | Statement::Loop(_) => {
+                self.loops += 1;
+                let result = walk_statement(self, statement);
+                self.loops -= 1;
+                result
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+    fn visit_expr(&mut self, expr: &mut Expr) -> std::result::Result<(), Self::Error> {
+        match expr {
+            Expr::LambdaExpr(_) => Ok(()),
+            _ => walk_expr(self, expr),
+        }
+    }
+    fn visit_fn(&mut self, _fn_def: &mut Fn) -> std::result::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Destination of the value of a block like expression, see
+/// [`LuaCompiler::compile_value`].
+#[derive(Clone)]
+enum Sink {
+    Discard,
+    Return,
+    Assign(AssignmentTarget),
+}
+
 pub struct LuaCompiler {
     code: IndentedBuilder,
     options: CompilerOptions,
     module_root_expr: Expr,
+    /// Traits declared so far in this unit.
+    traits: HashMap<String, TraitDef>,
+    /// Methods available to the instances of each class declared so far.
+    classes: HashMap<String, Vec<String>>,
+    /// Whether the [`AWAIT_RUNTIME`] helper has to be emitted.
+    uses_await: bool,
+    /// Whether the [`INSTANCE_OF_RUNTIME`] helper has to be emitted.
+    uses_instance_of: bool,
+    /// Whether the [`TUPLE_VALUES_RUNTIME`] helper has to be emitted.
+    uses_tuple_values: bool,
+    scopes: Vec<Scope>,
+    /// Loops compiled so far in this unit.
+    loop_count: usize,
+    /// Comprehensions compiled so far in this unit, to name their tables.
+    comprehension_count: usize,
+    /// Matches compiled so far in this unit, to name their end labels.
+    match_count: usize,
 }
 impl LuaCompiler {
     pub fn new() -> Self {
@@ -29,6 +227,15 @@ impl LuaCompiler {
             module_root_expr: Identifier::new("__modules__", false),
             code: IndentedBuilder::new(),
             options: Default::default(),
+            traits: HashMap::new(),
+            classes: HashMap::new(),
+            uses_await: false,
+            uses_instance_of: false,
+            uses_tuple_values: false,
+            scopes: vec![Scope::default()],
+            loop_count: 0,
+            comprehension_count: 0,
+            match_count: 0,
         }
     }
     fn compile_call(&mut self, call: Call) -> Result {
@@ -36,41 +243,127 @@ impl LuaCompiler {
             mut target,
             arguments,
             is_null_safe,
+            span,
         } = call;
+        if is_null_safe {
+            return Err(Self::unlowered(span));
+        }
         if let Expr::Member(member) = &mut *target {
             if let MemberOp::Member = member.op {
                 member.op = MemberOp::Dispatch;
             }
         }
-        if is_null_safe {
-            let original = target.clone();
-            target = Box::new(Bop::new(
-                Bop::new(
-                    *original.clone(),
-                    Operator::Neq,
-                    TupleLiteral::unit().into_expr(),
-                ),
-                Operator::And,
-                *original,
-            ));
+        // Literals can't be called in Lua without parentheses.
+        if let Expr::LambdaExpr(_)
+        | Expr::MapLiteral(_)
+        | Expr::ArrayLiteral(_)
+        | Expr::TupleLiteral(_)
+        | Expr::SatString(_)
+        | Expr::InterpolatedString(_) = &*target
+        {
+            self.code.write("(");
+            self.compile_expr(*target)?;
+            self.code.write(")");
+        } else {
+            self.compile_expr(*target)?;
         }
-        self.compile_expr(*target)?;
         self.code.write("(");
-        let mut args = arguments.into_iter();
-        if let Some(first) = args.next() {
+        if Self::has_inner_spread(&arguments) {
+            self.compile_spread_concat(arguments, true)?;
+        } else {
+            self.compile_expr_list(arguments)?;
+        }
+        self.code.write(")");
+        Ok(())
+    }
+    fn compile_expr_list(&mut self, values: Vec<Expr>) -> Result {
+        let mut iter = values.into_iter();
+        if let Some(first) = iter.next() {
             self.compile_expr(first)?;
         }
-        for expr in args {
+        for value in iter {
             self.code.write(", ");
-            self.compile_expr(expr)?;
+            self.compile_expr(value)?;
+        }
+        Ok(())
+    }
+    /// A spread in the last position expands in place, Lua truncates it to a
+    /// single value anywhere else.
+    fn has_inner_spread(values: &[Expr]) -> bool {
+        values
+            .iter()
+            .rev()
+            .skip(1)
+            .any(|value| matches!(value, Expr::Spread(_)))
+    }
+    /// Builds a table out of `values`, expanding every spread in order, or
+    /// gives back its values when `unpack`. Packed parts (varargs) keep their
+    /// `nil`s, as their length is taken from `n`.
+    fn compile_spread_concat(&mut self, values: Vec<Expr>, unpack: bool) -> Result {
+        // Consecutive plain values are packed in a table of their own.
+        let mut parts = vec![];
+        let mut group = vec![];
+        for value in values {
+            match value {
+                Expr::Spread(Spread { expr, .. }) => {
+                    if !group.is_empty() {
+                        parts.push(Self::packed(std::mem::take(&mut group)));
+                    }
+                    parts.push(*expr);
+                }
+                value => group.push(value),
+            }
+        }
+        if !group.is_empty() {
+            parts.push(Self::packed(group));
+        }
+        self.code
+            .write("(function(...)")
+            .push()
+            .line()
+            .write("local __spread__, __length__ = {}, 0;")
+            .line()
+            .write("for _, part in ipairs({ ... }) do")
+            .push()
+            .line()
+            .write("local length = part.n or #part;")
+            .line()
+            .write("table.move(part, 1, length, __length__ + 1, __spread__);")
+            .line()
+            .write("__length__ = __length__ + length;")
+            .pop()
+            .line()
+            .write("end")
+            .line();
+        if unpack {
+            self.code
+                .write("return table.unpack(__spread__, 1, __length__);");
+        } else {
+            self.code.write("return __spread__;");
         }
+        self.code.pop().line().write("end)(");
+        self.compile_expr_list(parts)?;
         self.code.write(")");
         Ok(())
     }
+    fn packed(values: Vec<Expr>) -> Expr {
+        Call::new(
+            Member::new(
+                Identifier::new("table", false),
+                MemberOp::Static,
+                Identifier::new("pack", false),
+            ),
+            values,
+            false,
+        )
+    }
     fn compile_number(&mut self, num: Number) -> Result {
         match num {
-            Number::Int(value) => self.code.write(value),
-            Number::Float(value) => self.code.write(value),
+            // Lua reads `-9223372036854775808` as the negation of a float.
+            Number::Int(i64::MIN, _) => self.code.write("math.mininteger"),
+            Number::Int(value, _) => self.code.write(value),
+            // Debug keeps the fraction (`2.0`) and uses exponents when shorter.
+            Number::Float(value, _) => self.code.write(format!("{value:?}")),
         };
         Ok(())
     }
@@ -136,7 +429,9 @@ impl LuaCompiler {
         format!("__{value}__")
     }
     fn compile_identifier(&mut self, ident: Identifier) -> Result {
-        let Identifier { value, is_escaped } = ident;
+        let Identifier {
+            value, is_escaped, ..
+        } = ident;
         if is_escaped {
             let value = Self::translate_identifier(value);
             self.code.write(value);
@@ -195,9 +490,11 @@ impl LuaCompiler {
             target: Box::new(Expr::Identifier(Identifier {
                 value: format!("`{value}`"),
                 is_escaped: true,
+                span: Span::default(),
             })),
             arguments,
             is_null_safe: false,
+            span: Span::default(),
         })?;
         Ok(())
     }
@@ -214,14 +511,62 @@ impl LuaCompiler {
     //     self.code.write(")");
     //     Ok(())
     // }
+    /// Binding power of the Lua operator a binary expression compiles to, and
+    /// whether it is right associative. Other operators compile to calls.
+    fn lua_precedence(op: &Operator) -> Option<(u8, bool)> {
+        let precedence = match op {
+            Operator::Or => 1,
+            Operator::And => 2,
+            Operator::Lt
+            | Operator::LtEq
+            | Operator::Gt
+            | Operator::GtEq
+            | Operator::Eq
+            | Operator::Neq => 3,
+            Operator::BOr => 4,
+            Operator::BXor => 5,
+            Operator::BAnd => 6,
+            Operator::LShift | Operator::RShift => 7,
+            Operator::StrCat => return Some((8, true)),
+            Operator::Add | Operator::Sub => 9,
+            Operator::Mul | Operator::Div => 10,
+            Operator::Pow => return Some((12, true)),
+            _ => return None,
+        };
+        Some((precedence, false))
+    }
+    /// Operands are parenthesized when they bind looser than `min`, as
+    /// grouping parentheses are not kept in the tree.
+    fn compile_operand(&mut self, operand: Expr, min: u8) -> Result {
+        let precedence = match &operand {
+            Expr::Bop(bop) => Self::lua_precedence(&bop.op).map(|(precedence, _)| precedence),
+            Expr::Uop(uop) => Self::infer_native_operator(&uop.op).map(|_| 11),
+            _ => None,
+        };
+        if precedence.is_some_and(|precedence| precedence < min) {
+            self.code.write("(");
+            self.compile_expr(operand)?;
+            self.code.write(")");
+            Ok(())
+        } else {
+            self.compile_expr(operand)
+        }
+    }
     fn compile_binary_expr(&mut self, bop: Bop) -> Result {
-        let Bop { left, op, right } = bop;
+        let Bop {
+            left,
+            op,
+            right,
+            span,
+            ..
+        } = bop;
+        let (precedence, is_right_assoc) = Self::lua_precedence(&op).unwrap_or((0, false));
         if let Some(op) = Self::infer_native_operator(&op) {
-            self.compile_expr(*left)?;
+            self.compile_operand(*left, precedence + is_right_assoc as u8)?;
             self.code.write(" ");
             self.code.write(op);
             self.code.write(" ");
-            self.compile_expr(*right)?;
+            self.compile_operand(*right, precedence + !is_right_assoc as u8)?;
         } else {
             match op {
                 Operator::LShiftRot => {
@@ -232,7 +577,8 @@ impl LuaCompiler {
                 }
                 Operator::Range => self.compile_custom_operator("..".into(), left, Some(right))?,
                 Operator::StrCat => {
-                    self.compile_expr(*left)?;
+                    // Concatenation is associative, so chains are left flat.
+                    self.compile_operand(*left, precedence)?;
                     self.code.write(" .. tostring(");
                     self.compile_expr(*right)?;
                     self.code.write(")");
@@ -240,6 +586,7 @@ impl LuaCompiler {
                 Operator::Custom(value) => {
                     self.compile_custom_operator(value, left, Some(right))?
                 }
+                Operator::Coalesce => return Err(Self::unlowered(span)),
                 _ => panic!(
                     "Unhandled operator panic! This shouldn't be reachable, report this bug please."
                 ),
@@ -248,15 +595,18 @@ impl LuaCompiler {
         Ok(())
     }
     fn compile_member_access(&mut self, value: Member) -> Result {
-        let Member { target, op, field } = value;
-        self.compile_expr(*target.clone())?;
+        let Member {
+            target,
+            op,
+            field,
+            span,
+        } = value;
+        if let MemberOp::CoalesceMember = op {
+            return Err(Self::unlowered(span));
+        }
+        self.compile_expr(*target)?;
         match op {
-            MemberOp::Member => {
-                self.code.write(".");
-            }
-            MemberOp::CoalesceMember => {
-                self.code.write(" ~= nil and ");
-                self.compile_expr(*target)?;
+            MemberOp::Member | MemberOp::CoalesceMember => {
                 self.code.write(".");
             }
             MemberOp::Static => {
@@ -274,12 +624,12 @@ impl LuaCompiler {
             target,
             arguments,
             is_null_safe,
+            span,
         } = expr;
-        self.compile_expr(*target.clone())?;
         if is_null_safe {
-            self.code.write(" ~= nil and ");
-            self.compile_expr(*target)?;
+            return Err(Self::unlowered(span));
         }
+        self.compile_expr(*target)?;
         for item in arguments {
             self.code.write("[");
             self.compile_expr(item)?;
@@ -295,10 +645,36 @@ impl LuaCompiler {
         }
         Ok(())
     }
+    /// Interpolations are lowered the same way `++` is, to a concatenation
+    /// that stringifies every embedded expression.
+    fn compile_interpolated_string(&mut self, expr: InterpolatedString) -> Result {
+        self.code.write("(");
+        let mut iter = expr.fragments.into_iter();
+        let mut next = iter.next();
+        while let Some(fragment) = next {
+            match fragment {
+                StringFragment::Literal(value) => self.compile_string(SatString {
+                    value,
+                    span: Span::default(),
+                })?,
+                StringFragment::Expr(expr) => {
+                    self.code.write("tostring(");
+                    self.compile_expr(expr)?;
+                    self.code.write(")");
+                }
+            }
+            next = iter.next();
+            if next.is_some() {
+                self.code.write(" .. ");
+            }
+        }
+        self.code.write(")");
+        Ok(())
+    }
     fn compile_boolean(&mut self, expr: Boolean) -> Result {
         let value = match expr {
-            Boolean::True => "true",
-            Boolean::False => "false",
+            Boolean::True(_) => "true",
+            Boolean::False(_) => "false",
         };
         self.code.write(value);
         Ok(())
@@ -340,21 +716,42 @@ impl LuaCompiler {
         Ok(())
     }
     fn compile_array(&mut self, array_literal: ArrayLiteral) -> Result {
+        let values = array_literal.values;
+        if Self::has_inner_spread(&values) {
+            if self.options.use_std_collections {
+                self.code.write("std.Array(");
+                self.compile_spread_concat(values, false)?;
+                self.code.write(")");
+            } else {
+                self.compile_spread_concat(values, false)?;
+            }
+            return Ok(());
+        }
         if self.options.use_std_collections {
             self.code.write("std.Array ");
         }
         self.code.write("{ ");
-        let mut iter = array_literal.values.into_iter();
-        if let Some(first) = iter.next() {
-            self.compile_expr(first)?;
-        }
-        for value in iter {
-            self.code.write(format!(", "));
-            self.compile_expr(value)?;
-        }
+        self.compile_expr_list(values)?;
         self.code.write(" }");
         Ok(())
     }
+    /// Spreading a name also covers the tables packed from varargs, whose
+    /// length, `n`, counts the `nil`s as well. Lua takes the border of the
+    /// table when there is no `n`.
+    fn compile_spread(&mut self, spread: Spread) -> Result {
+        self.code.write("table.unpack(");
+        match *spread.expr {
+            Expr::Identifier(identifier) => {
+                self.compile_identifier(identifier.clone())?;
+                self.code.write(", 1, ");
+                self.compile_identifier(identifier)?;
+                self.code.write(".n");
+            }
+            expr => self.compile_expr(expr)?,
+        }
+        self.code.write(")");
+        Ok(())
+    }
     fn compile_tuple(&mut self, tuple_literal: TupleLiteral) -> Result {
         if tuple_literal.is_unit() {
             if self.options.unit_interop {
@@ -383,58 +780,116 @@ impl LuaCompiler {
         self.code.write(" }");
         Ok(())
     }
+    fn compile_params(&mut self, params: &[Param]) -> Result {
+        let mut iter = params.iter().enumerate();
+        if let Some((i, first)) = iter.next() {
+            self.compile_param(i, first)?;
+        }
+        for (i, param) in iter {
+            self.code.write(", ");
+            self.compile_param(i, param)?;
+        }
+        Ok(())
+    }
+    fn compile_param(&mut self, index: usize, param: &Param) -> Result {
+        if param.is_variadic {
+            self.code.write("...");
+            Ok(())
+        } else {
+            self.compile_identifier(Self::param_name(index, param))
+        }
+    }
+    /// Patterns are received in a synthetic parameter, destructured by the
+    /// initializers.
+    fn param_name(index: usize, param: &Param) -> Identifier {
+        match &param.name {
+            Destructure::Identifier(name) => name.clone(),
+            _ => Identifier::new(format!("__param_{index}__"), false).unwrap_identifier(),
+        }
+    }
     fn compile_param_initializers(
         params: Vec<Param>,
         body: Vec<Statement>,
     ) -> std::result::Result<Vec<Statement>, CompilerError> {
-        let out = params
-            .into_iter()
-            .filter_map(|Param { name, init, .. }| {
-                init.map(move |init| {
-                    let condition = Bop::new(
-                        name.clone().into_expr(),
-                        Operator::Eq,
-                        Expr::TupleLiteral(TupleLiteral::unit()),
-                    );
-                    let body = vec![
-                        Assignment::new(AssignmentTarget::Identifier(name), None, init)
-                            .into_statement(),
-                    ];
-                    Statement::IfStatement(IfStatement {
-                        condition: Box::new(condition),
-                        body,
-                        else_if_blocks: vec![],
-                        else_block: None,
-                    })
-                })
-            })
-            .chain(body.into_iter())
-            .collect();
+        // Variadic arguments are packed into a table as the first thing.
+        let rest = params
+            .iter()
+            .enumerate()
+            .find(|(_, param)| param.is_variadic)
+            .map(|(i, param)| {
+                let pack = Call::new(
+                    Member::new(
+                        Identifier::new("table", false),
+                        MemberOp::Static,
+                        Identifier::new("pack", false),
+                    ),
+                    vec![Identifier::new("...", false)],
+                    false,
+                );
+                Let::new(Self::param_name(i, param), DefModifiers::new(), pack).into_statement()
+            });
+        // Then defaults are given, and patterns destructured, in order.
+        let initializers = params.into_iter().enumerate().flat_map(|(i, param)| {
+            let name = Self::param_name(i, &param);
+            let default = param.init.map(|init| {
+                let condition = Bop::new(
+                    name.clone().into_expr(),
+                    Operator::Eq,
+                    Expr::TupleLiteral(TupleLiteral::unit()),
+                );
+                let body = vec![
+                    Assignment::new(AssignmentTarget::Identifier(name.clone()), None, init)
+                        .into_statement(),
+                ];
+                IfStatement::new(condition, body, vec![], None)
+            });
+            let destructure = match param.name {
+                Destructure::Identifier(_) => None,
+                pattern => {
+                    let mut destructure =
+                        Let::new(name.clone(), DefModifiers::new(), name.into_expr());
+                    destructure.name = pattern;
+                    Some(destructure.into_statement())
+                }
+            };
+            default.into_iter().chain(destructure)
+        });
+        let out = rest.into_iter().chain(initializers).chain(body).collect();
         Ok(out)
     }
+    /// Calling an async function does not run it: it hands back a suspended
+    /// coroutine (the task), which is driven by `__await__`.
+    fn into_task(modifiers: &DefModifiers, body: Vec<Statement>) -> Vec<Statement> {
+        if !modifiers.is_async() {
+            return body;
+        }
+        let create = Member::new(
+            Identifier::new("coroutine", false),
+            MemberOp::Static,
+            Identifier::new("create", false),
+        );
+        vec![Return::new(Call::new(
+            create,
+            vec![LambdaExpr::new(vec![], body)],
+            false,
+        ))]
+    }
     fn compile_lambda(&mut self, lambda_expr: LambdaExpr) -> Result {
         self.code.write("function(");
-        let mut iter = lambda_expr.params.iter();
-        if let Some(first) = iter.next() {
-            self.compile_identifier(first.name.clone())?;
-        }
-        for param in iter {
-            self.code.write(", ");
-            self.compile_identifier(param.name.clone())?;
-        }
+        self.compile_params(&lambda_expr.params)?;
         self.code.write(")").push();
         let body = lambda_expr.body;
         let body = Self::compile_param_initializers(lambda_expr.params, body)?;
-        self.compile_program(body)?;
+        self.compile_function_body(body)?;
         self.code.pop().line().write("end");
         Ok(())
     }
     fn compile_unary(&mut self, uop: Uop) -> Result {
-        let Uop { op, expr } = uop;
+        let Uop { op, expr, .. } = uop;
         if let Some(op) = Self::infer_native_operator(&op) {
             self.code.write(op);
             self.code.write(" ");
-            self.compile_expr(*expr)?;
+            self.compile_operand(*expr, 12)?;
         } else {
             match op {
                 Operator::Custom(value) => self.compile_custom_operator(value, expr, None)?,
@@ -454,12 +909,26 @@ impl LuaCompiler {
             Expr::Member(value) => self.compile_member_access(value)?,
             Expr::ArrayAccess(value) => self.compile_array_access(value)?,
             Expr::SatString(value) => self.compile_string(value)?,
+            Expr::InterpolatedString(value) => self.compile_interpolated_string(value)?,
             Expr::Boolean(value) => self.compile_boolean(value)?,
             Expr::Uop(uop) => self.compile_unary(uop)?,
             Expr::LambdaExpr(lambda_expr) => self.compile_lambda(lambda_expr)?,
             Expr::MapLiteral(map_literal) => self.compile_map(map_literal)?,
             Expr::ArrayLiteral(array_literal) => self.compile_array(array_literal)?,
             Expr::TupleLiteral(tuple_literal) => self.compile_tuple(tuple_literal)?,
+            Expr::Spread(spread) => self.compile_spread(spread)?,
+            Expr::Await(Await { expr, .. }) => {
+                self.uses_await = true;
+                self.code.write("__await__(");
+                self.compile_expr(*expr)?;
+                self.code.write(")");
+            }
+            expr @ (Expr::Match(_)
+            | Expr::IfExpr(_)
+            | Expr::Block(_)
+            | Expr::ArrayComprehension(_)
+            | Expr::MapComprehension(_)) => self.compile_block_expr(expr)?,
+            Expr::MacroCall(call) => return Err(Self::unexpanded(call.span)),
         }
         Ok(())
     }
@@ -469,13 +938,16 @@ impl LuaCompiler {
             body,
             else_if_blocks,
             else_block,
+            ..
         } = stmt;
         self.code.write("if ");
         self.compile_expr(*condition)?;
         self.code.write(" then").push();
         self.compile_program(body)?;
         for else_if in else_if_blocks.into_iter() {
-            let ElseIf { condition, body } = else_if;
+            let ElseIf {
+                condition, body, ..
+            } = else_if;
             self.code.pop().line().write("elseif ");
             self.compile_expr(*condition)?;
             self.code.write(" then").push();
@@ -494,7 +966,10 @@ impl LuaCompiler {
         expr: &Box<Expr>,
         body: &Vec<Statement>,
     ) -> std::result::Result<bool, CompilerError> {
-        let Expr::Bop(Bop { left, op, right }) = &**expr else {
+        let Expr::Bop(Bop {
+            left, op, right, ..
+        }) = &**expr
+        else {
             return Ok(false);
         };
         if Operator::Range != *op {
@@ -522,6 +997,7 @@ impl LuaCompiler {
             target,
             arguments,
             is_null_safe,
+            ..
         }) = &**expr
         else {
             return Ok(false);
@@ -545,30 +1021,61 @@ impl LuaCompiler {
         let DestructureEntry::Identifier(k) = k else {
             return Ok(false);
         };
+        let value = match &elements[1] {
+            DestructureEntry::Identifier(v) => Destructure::Identifier(v.clone()),
+            DestructureEntry::Array(items) => Destructure::Array(items.clone()),
+            DestructureEntry::Map(items) => Destructure::Map(items.clone()),
+            DestructureEntry::Tuple(items) => Destructure::Tuple(items.clone()),
+            _ => return Ok(false),
+        };
         self.compile_identifier(k.clone())?;
-        if let DestructureEntry::Identifier(v) = &elements[1] {
-            self.code.write(", ");
-            self.compile_identifier(v.clone())?;
-            self.code.write(" in ");
-        } else {
-            self.code.write(", __destructure_value__ in ");
-        }
+        self.code.write(", ");
+        let body = self.compile_loop_variable(value, body.clone())?;
+        self.code.write(" in ");
         self.compile_call(Call {
             target: target.clone(),
             arguments: arguments.clone(),
             is_null_safe: false,
+            span: Span::default(),
         })?;
         self.code.write(" do").push();
-        self.build_loop_body(body.clone())?;
+        self.build_loop_body(body)?;
         Ok(true)
     }
+    /// Names the loop variable after the pattern when it is a plain name,
+    /// otherwise the value is destructured into it at the start of the body.
+    /// Comprehensions over iterators are lowered to loops taking this path.
+    fn compile_loop_variable(
+        &mut self,
+        assignment: Destructure,
+        body: Vec<Statement>,
+    ) -> std::result::Result<Vec<Statement>, CompilerError> {
+        if let Destructure::Identifier(identifier) = assignment {
+            self.compile_identifier(identifier)?;
+            return Ok(body);
+        }
+        self.code.write("__destructure_value__");
+        let mut binding = Let::new(
+            Identifier::new("__destructure_value__", false).unwrap_identifier(),
+            DefModifiers::new(),
+            Identifier::new("__destructure_value__", false),
+        );
+        binding.name = assignment;
+        Ok(std::iter::once(binding.into_statement())
+            .chain(body)
+            .collect())
+    }
     /// To avoid ambiguities and further code generation complexities, Saturnus offers
     /// assignments only in the form of statements.
     fn compile_assignment(&mut self, stmt: Assignment) -> Result {
-        let Assignment { left, right, op } = stmt;
+        let Assignment {
+            left, right, op, ..
+        } = stmt;
         if let Some(op) = op {
             let right = Bop::new(left.clone().to_expr(), op, *right);
             self.compile_assignment(Assignment::new(left, None, right))?;
+        } else if Self::is_block_like(&right) {
+            self.compile_value(*right, Sink::Assign(left))?;
         } else {
             match left {
                 AssignmentTarget::Member(member) => self.compile_member_access(member)?,
@@ -583,27 +1090,124 @@ impl LuaCompiler {
         }
         Ok(())
     }
+    /// Destructures a positional entry (of an array or a tuple) from its value.
+    fn compile_destructure_entry(&mut self, root: Expr, entry: DestructureEntry) -> Result {
+        match entry {
+            DestructureEntry::Identifier(identifier) => {
+                if identifier.is_void() {
+                    return Ok(());
+                }
+                let left = AssignmentTarget::Identifier(identifier.clone());
+                self.compile_statement(Assignment::new(left, None, root).into_statement())?;
+            }
+            DestructureEntry::Array(items) => self.compile_array_destructure(root, items)?,
+            DestructureEntry::Map(items) => self.compile_map_destructure(root, items)?,
+            DestructureEntry::Tuple(items) => self.compile_tuple_destructure(root, items)?,
+            DestructureEntry::Default(entry, value) => {
+                self.compile_default_entry(root, *entry, *value)?
+            }
+            DestructureEntry::Aliasing(_, _) | DestructureEntry::Rest(_) => panic!(
+                "This branch should not be reachable, there's a problem in the AST. Please report this bug."
+            ),
+        }
+        Ok(())
+    }
+    /// Names are assigned first and then tested, nested patterns are
+    /// destructured from a temporary holding either the value or the default.
+    fn compile_default_entry(
+        &mut self,
+        root: Expr,
+        entry: DestructureEntry,
+        value: Expr,
+    ) -> Result {
+        let is_unit = |name: &Expr| {
+            Bop::new(
+                name.clone(),
+                Operator::Eq,
+                Expr::TupleLiteral(TupleLiteral::unit()),
+            )
+        };
+        match entry {
+            DestructureEntry::Identifier(identifier) => {
+                if identifier.is_void() {
+                    return Ok(());
+                }
+                let name = identifier.clone().into_expr();
+                self.compile_destructure_entry(
+                    root,
+                    DestructureEntry::Identifier(identifier.clone()),
+                )?;
+                let assign = Assignment::new(AssignmentTarget::Identifier(identifier), None, value);
+                self.compile_statement(IfStatement::new(
+                    is_unit(&name),
+                    vec![assign.into_statement()],
+                    vec![],
+                    None,
+                ))?;
+            }
+            entry => {
+                let temporary = Identifier::new("__destructure_default__", false);
+                self.code.line().write("do").push();
+                self.compile_statement(
+                    Let::new(
+                        temporary.clone().unwrap_identifier(),
+                        DefModifiers::new(),
+                        root,
+                    )
+                    .into_statement(),
+                )?;
+                let assign = Assignment::new(
+                    temporary
+                        .clone()
+                        .unwrap_identifier()
+                        .into_assignmenttarget(),
+                    None,
+                    value,
+                );
+                self.compile_statement(IfStatement::new(
+                    is_unit(&temporary),
+                    vec![assign.into_statement()],
+                    vec![],
+                    None,
+                ))?;
+                self.compile_destructure_entry(temporary, entry)?;
+                self.code.pop().line().write("end");
+            }
+        }
+        Ok(())
+    }
     fn compile_array_destructure(&mut self, root: Expr, items: Vec<DestructureEntry>) -> Result {
         let mut i = 0;
         for entry in items {
             i += 1;
-            let root = root.clone().array_access(Number::Int(i).into_expr());
-            match entry {
-                DestructureEntry::Identifier(identifier) => {
-                    if identifier.is_void() {
-                        continue;
-                    }
-                    let left = AssignmentTarget::Identifier(identifier.clone());
-                    self.compile_statement(Assignment::new(left, None, root).into_statement())?;
-                }
-                DestructureEntry::Array(items) => self.compile_array_destructure(root, items)?,
-                DestructureEntry::Map(items) => self.compile_map_destructure(root, items)?,
-                DestructureEntry::Tuple(items) => self.compile_tuple_destructure(root, items)?,
-                DestructureEntry::Aliasing(_, _) => panic!(
-                    "This branch should not be reachable, there's a problem in the AST. Please report this bug."
-                ),
+            if let DestructureEntry::Rest(name) = entry {
+                self.compile_array_rest(root.clone(), i, name)?;
+                continue;
             }
+            let root = root
+                .clone()
+                .array_access(Number::Int(i, Span::default()).into_expr());
+            self.compile_destructure_entry(root, entry)?;
+        }
+        Ok(())
+    }
+    /// `[a, ...rest]`, the items from `from` on are moved to a new array.
+    fn compile_array_rest(&mut self, root: Expr, from: i64, name: Identifier) -> Result {
+        if name.is_void() {
+            return Ok(());
         }
+        self.code.line();
+        self.compile_identifier(name)?;
+        self.code.write(" = table.move(");
+        self.compile_expr(root.clone())?;
+        self.code.write(format!(", {from}, #"));
+        self.compile_expr(root)?;
+        self.code.write(", 1, ");
+        self.compile_array(ArrayLiteral {
+            values: vec![],
+            span: Span::default(),
+        })?;
+        self.code.write(");");
         Ok(())
     }
     fn compile_map_entry(
@@ -635,49 +1239,147 @@ impl LuaCompiler {
                 let root = root.add_member(identifier);
                 self.compile_map_entry(root, *destructure_entry, true)?;
             }
+            DestructureEntry::Default(entry, value) => {
+                let root = match &*entry {
+                    DestructureEntry::Identifier(identifier) if !skip_member => {
+                        root.add_member(identifier.clone())
+                    }
+                    _ => root,
+                };
+                self.compile_default_entry(root, *entry, *value)?;
+            }
+            DestructureEntry::Rest(_) => panic!(
+                "This branch should not be reachable, there's a problem in the AST. Please report this bug."
+            ),
         }
         Ok(())
     }
     fn compile_map_destructure(&mut self, root: Expr, items: Vec<DestructureEntry>) -> Result {
+        // The keys taken by the other entries are left out of the rest.
+        let keys = items
+            .iter()
+            .filter_map(|entry| match entry {
+                DestructureEntry::Identifier(key) | DestructureEntry::Aliasing(key, _) => {
+                    Some(key.value.clone())
+                }
+                DestructureEntry::Default(entry, _) => match &**entry {
+                    DestructureEntry::Identifier(key) => Some(key.value.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
         for entry in items {
             let root = root.clone();
+            if let DestructureEntry::Rest(name) = entry {
+                self.compile_map_rest(root, &keys, name)?;
+                continue;
+            }
             self.compile_map_entry(root, entry, false)?;
         }
         Ok(())
     }
+    /// `{ a, ...rest }`, the entries of any other key are copied to a new map.
+    fn compile_map_rest(&mut self, root: Expr, keys: &[String], name: Identifier) -> Result {
+        if name.is_void() {
+            return Ok(());
+        }
+        self.code.line();
+        self.compile_identifier(name.clone())?;
+        self.code.write(" = ");
+        self.compile_map(MapLiteral {
+            entries: vec![],
+            span: Span::default(),
+        })?;
+        self.code
+            .write(";")
+            .line()
+            .write("for __key__, __value__ in pairs(");
+        self.compile_expr(root)?;
+        self.code.write(") do").push();
+        if !keys.is_empty() {
+            let condition = keys
+                .iter()
+                .map(|key| format!("__key__ ~= \"{key}\""))
+                .collect::<Vec<_>>()
+                .join(" and ");
+            self.code
+                .line()
+                .write(format!("if {condition} then"))
+                .push();
+        }
+        self.code.line();
+        self.compile_identifier(name)?;
+        self.code.write("[__key__] = __value__;");
+        if !keys.is_empty() {
+            self.code.pop().line().write("end");
+        }
+        self.code.pop().line().write("end");
+        Ok(())
+    }
     fn compile_tuple_destructure(&mut self, root: Expr, items: Vec<DestructureEntry>) -> Result {
         let mut i = 0;
         for entry in items {
+            if let DestructureEntry::Rest(name) = entry {
+                self.compile_tuple_rest(root.clone(), i, name)?;
+                continue;
+            }
             let root = root.clone().add_member(Identifier {
                 value: format!("__{i}"),
                 is_escaped: false,
+                span: Span::default(),
             });
             i += 1;
-            match entry {
-                DestructureEntry::Identifier(identifier) => {
-                    if identifier.is_void() {
-                        continue;
-                    }
-                    let left = AssignmentTarget::Identifier(identifier.clone());
-                    self.compile_statement(Assignment::new(left, None, root).into_statement())?;
-                }
-                DestructureEntry::Array(items) => self.compile_array_destructure(root, items)?,
-                DestructureEntry::Map(items) => self.compile_map_destructure(root, items)?,
-                DestructureEntry::Tuple(items) => self.compile_tuple_destructure(root, items)?,
-                DestructureEntry::Aliasing(_, _) => panic!(
-                    "This branch should not be reachable, there's a problem in the AST. Please report this bug."
-                ),
-            }
+            self.compile_destructure_entry(root, entry)?;
         }
         Ok(())
     }
-    /// Compiles expressions like `let [a, b, c] = arr;`
-    fn compile_destructure_assignment_list(&mut self, destructure: Destructure) -> Result {
-        let root = Identifier::new("__destructure_target__", false);
-        match destructure {
-            Destructure::Identifier(ident) => {
-                self.code.line();
-                self.compile_let(Let::new(ident, DefModifiers::new(), root))?;
+    /// `(a, ...rest)`, the fields from `__{from}` on are moved to a new tuple.
+    /// The size of a tuple is not known here, so fields are read until unit.
+    fn compile_tuple_rest(&mut self, root: Expr, from: usize, name: Identifier) -> Result {
+        if name.is_void() {
+            return Ok(());
+        }
+        self.code.line();
+        self.compile_identifier(name.clone())?;
+        self.code.write(" = ");
+        if self.options.use_std_collections {
+            self.code.write("std.Tuple ");
+        }
+        self.code
+            .write("{  };")
+            .line()
+            .write(format!("for __index__ = {from}, math.huge do"))
+            .push()
+            .line()
+            .write("local __value__ = ");
+        self.compile_expr(root)?;
+        self.code
+            .write("[\"__\" .. __index__];")
+            .line()
+            .write("if __value__ == nil then")
+            .push()
+            .line()
+            .write("break;")
+            .pop()
+            .line()
+            .write("end")
+            .line();
+        self.compile_identifier(name)?;
+        self.code
+            .write(format!("[\"__\" .. (__index__ - {from})] = __value__;"))
+            .pop()
+            .line()
+            .write("end");
+        Ok(())
+    }
+    /// Compiles expressions like `let [a, b, c] = arr;`
+    fn compile_destructure_assignment_list(&mut self, destructure: Destructure) -> Result {
+        let root = Identifier::new("__destructure_target__", false);
+        match destructure {
+            Destructure::Identifier(ident) => {
+                self.code.line();
+                self.compile_let(Let::new(ident, DefModifiers::new(), root))?;
             }
             Destructure::Array(items) => self.compile_array_destructure(root, items)?,
             Destructure::Map(items) => self.compile_map_destructure(root, items)?,
@@ -691,18 +1393,40 @@ impl LuaCompiler {
             type_def: _,
             initializer,
             modifiers,
+            annotations,
+            ..
         } = expr;
+        let initializer = initializer.map(|init| Self::decorate(&annotations.decorators, init));
         match name {
             Destructure::Identifier(identifier) => {
                 self.code.write("local ");
                 self.compile_identifier(identifier.clone())?;
-                if let Some(val) = initializer {
-                    self.code.write(" = ");
-                    self.compile_expr(val)?;
+                match initializer {
+                    // Declared first, then assigned from within the block.
+                    Some(val) if Self::is_block_like(&val) => {
+                        self.code.write(";").line();
+                        let target = AssignmentTarget::Identifier(identifier.clone());
+                        self.compile_value(val, Sink::Assign(target))?;
+                    }
+                    Some(val) => {
+                        if modifiers.is_const() && self.options.lua_version == LuaVersion::Lua54 {
+                            self.code.write(" <const>");
+                        }
+                        self.code.write(" = ");
+                        self.compile_expr(val)?;
+                        self.code.write(";");
+                    }
+                    None => {
+                        self.code.write(";");
+                    }
                 }
-                self.code.write(";");
                 self.export_symbol(&modifiers, identifier)?;
             }
+            Destructure::Tuple(entries)
+                if self.options.multiple_returns && matches!(initializer, Some(Expr::Call(_))) =>
+            {
+                self.compile_multiple_values_let(entries, initializer.unwrap(), &modifiers)?;
+            }
             other => {
                 self.code.write("local ");
                 let mut leaves = other.collect_leaves().into_iter().filter(|x| !x.is_void());
@@ -732,11 +1456,82 @@ impl LuaCompiler {
         }
         Ok(())
     }
+    /// `let (a, b) = f();` when calls return tuples as multiple values, see
+    /// `CompilerOptions::multiple_returns`. Nested patterns are destructured
+    /// from temporaries. Calls returning a tuple table are destructured
+    /// alike, through [`TUPLE_VALUES_RUNTIME`].
+    fn compile_multiple_values_let(
+        &mut self,
+        entries: Vec<DestructureEntry>,
+        call: Expr,
+        modifiers: &DefModifiers,
+    ) -> Result {
+        if let Some(rest) = entries
+            .iter()
+            .find(|entry| matches!(entry, DestructureEntry::Rest(_)))
+        {
+            return Err(CompilerError::SyntaxError(Box::new(Diagnostic::new(
+                "rest patterns can't collect multiple return values",
+                rest.span(),
+            ))));
+        }
+        self.uses_tuple_values = true;
+        let call = Call::new(
+            Identifier::new("__tuple_values__", false),
+            vec![(entries.len() as i64).as_expr(), call],
+            false,
+        );
+        let leaves = Destructure::Tuple(entries.clone())
+            .collect_leaves()
+            .into_iter()
+            .filter(|leaf| !leaf.is_void())
+            .collect::<Vec<_>>();
+        let names = entries
+            .iter()
+            .map(|entry| match entry {
+                DestructureEntry::Identifier(name) => Some(name.clone().into_expr()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        if let Some(names) = names {
+            self.code.write("local ");
+            self.compile_expr_list(names)?;
+            self.code.write(" = ");
+            self.compile_expr(call)?;
+            self.code.write(";");
+        } else {
+            let temporaries = (0..entries.len())
+                .map(|i| Identifier::new(format!("__destructure_{i}__"), false))
+                .collect::<Vec<_>>();
+            self.code.write("local ");
+            self.compile_expr_list(leaves.iter().cloned().map(IntoExpr::into_expr).collect())?;
+            self.code
+                .write(";")
+                .line()
+                .write("do")
+                .push()
+                .line()
+                .write("local ");
+            self.compile_expr_list(temporaries.clone())?;
+            self.code.write(" = ");
+            self.compile_expr(call)?;
+            self.code.write(";");
+            for (root, entry) in temporaries.into_iter().zip(entries) {
+                self.compile_destructure_entry(root, entry)?;
+            }
+            self.code.pop().line().write("end");
+        }
+        for leaf in leaves {
+            self.export_symbol(modifiers, leaf)?;
+        }
+        Ok(())
+    }
     fn build_loop_body(&mut self, body: Vec<Statement>) -> Result {
         self.compile_program(body)?;
+        let id = self.scope().loops.last().expect("Compiled in a loop").id;
         self.code
             .line()
-            .write("::loop_end::")
+            .write(format!("::loop_end_{id}::"))
             .pop()
             .line()
             .write("end");
@@ -747,6 +1542,7 @@ impl LuaCompiler {
             mut assignment,
             expr,
             body,
+            ..
         } = stmt;
         self.code.write("for ");
         // Try to optimize away by removing iterators:
@@ -756,44 +1552,581 @@ impl LuaCompiler {
             return Ok(());
         }
         // Not optimized, proceed with standard iterator control.
-        self.code.write("__destructure_value__ in ");
+        let body = self.compile_loop_variable(assignment, body)?;
+        self.code.write(" in ");
         self.compile_expr(*expr)?;
         self.code.write(" do").push();
-        self.compile_destructure_assignment_list(assignment)?;
         self.build_loop_body(body)?;
         Ok(())
     }
     fn compile_while(&mut self, stmt: While) -> Result {
-        let While { condition, body } = stmt;
+        let While {
+            condition, body, ..
+        } = stmt;
         self.code.write("while ");
         self.compile_expr(*condition)?;
         self.code.write(" do").push();
-        self.compile_program(body)?;
+        self.build_loop_body(body)
+    }
+    fn compile_loop(&mut self, stmt: Loop) -> Result {
+        self.code.write("while true do").push();
+        self.build_loop_body(stmt.body)
+    }
+    fn is_table(expr: Expr) -> Expr {
+        Bop::new(
+            Call::new(Identifier::new("type", false), vec![expr], false),
+            Operator::Eq,
+            SatString {
+                value: "table".into(),
+                span: Span::default(),
+            }
+            .into_expr(),
+        )
+    }
+    /// Lowers `pattern`, tested against `subject`, to the conditions that must
+    /// hold for it to match (in evaluation order) and the locals it binds.
+    fn lower_pattern(
+        &mut self,
+        pattern: Pattern,
+        subject: Expr,
+        conditions: &mut Vec<Expr>,
+        bindings: &mut Vec<(Identifier, Expr)>,
+    ) {
+        match pattern {
+            Pattern::Wildcard(_) => (),
+            Pattern::Literal(value) => conditions.push(Bop::new(subject, Operator::Eq, value)),
+            Pattern::Binding(identifier) => bindings.push((identifier, subject)),
+            Pattern::Array(items, _) => {
+                conditions.push(Self::is_table(subject.clone()));
+                conditions.push(Bop::new(
+                    Call::new(
+                        Identifier::new("rawlen", false),
+                        vec![subject.clone()],
+                        false,
+                    ),
+                    Operator::Eq,
+                    Number::Int(items.len() as i64, Span::default()).into_expr(),
+                ));
+                for (i, item) in items.into_iter().enumerate() {
+                    let root = subject
+                        .clone()
+                        .array_access(Number::Int(i as i64 + 1, Span::default()).into_expr());
+                    self.lower_pattern(item, root, conditions, bindings);
+                }
+            }
+            Pattern::Tuple(items, _) => {
+                conditions.push(Self::is_table(subject.clone()));
+                let field = |i: usize| {
+                    subject.clone().add_member(Identifier {
+                        value: format!("__{i}"),
+                        is_escaped: false,
+                        span: Span::default(),
+                    })
+                };
+                // Tuples of another arity, and other tables, have a field
+                // missing or one too many.
+                for i in 0..items.len() {
+                    conditions.push(Bop::new(
+                        field(i),
+                        Operator::Neq,
+                        TupleLiteral::unit().into_expr(),
+                    ));
+                }
+                conditions.push(Bop::new(
+                    field(items.len()),
+                    Operator::Eq,
+                    TupleLiteral::unit().into_expr(),
+                ));
+                for (i, item) in items.into_iter().enumerate() {
+                    self.lower_pattern(item, field(i), conditions, bindings);
+                }
+            }
+            Pattern::Map(entries, _) => {
+                conditions.push(Self::is_table(subject.clone()));
+                self.lower_field_patterns(entries, subject, conditions, bindings);
+            }
+            Pattern::Class(class, entries, _) => {
+                self.uses_instance_of = true;
+                conditions.push(Call::new(
+                    Identifier::new("__instance_of__", false),
+                    vec![subject.clone(), class.into_expr()],
+                    false,
+                ));
+                self.lower_field_patterns(entries, subject, conditions, bindings);
+            }
+            Pattern::Variant(enum_name, variant, items, _) => {
+                conditions.push(Bop::new(
+                    Call::new(
+                        Identifier::new("getmetatable", false),
+                        vec![subject.clone()],
+                        false,
+                    ),
+                    Operator::Eq,
+                    enum_name
+                        .into_expr()
+                        .add_member(variant)
+                        .add_member(Identifier::new("__meta__", false).unwrap_identifier()),
+                ));
+                for (i, item) in items.into_iter().enumerate() {
+                    let root = subject.clone().add_member(Identifier {
+                        value: format!("__{i}"),
+                        is_escaped: false,
+                        span: Span::default(),
+                    });
+                    self.lower_pattern(item, root, conditions, bindings);
+                }
+            }
+        }
+    }
+    /// Fields bound without further shape (`{ x }` or `{ x: _ }`) must be
+    /// present, that is not `nil`.
+    fn lower_field_patterns(
+        &mut self,
+        entries: Vec<(Identifier, Pattern)>,
+        subject: Expr,
+        conditions: &mut Vec<Expr>,
+        bindings: &mut Vec<(Identifier, Expr)>,
+    ) {
+        for (key, pattern) in entries {
+            let root = subject.clone().add_member(key);
+            if let Pattern::Binding(_) | Pattern::Wildcard(_) = pattern {
+                conditions.push(Bop::new(
+                    root.clone(),
+                    Operator::Neq,
+                    TupleLiteral::unit().into_expr(),
+                ));
+            }
+            self.lower_pattern(pattern, root, conditions, bindings);
+        }
+    }
+    /// Each arm is tested in order, the taken arm jumps past the rest unless
+    /// it already returns.
+    fn compile_match_arms(&mut self, id: usize, arms: Vec<MatchArm>, sink: Sink) -> Result {
+        let subject = Identifier::new("__match_subject__", false);
+        for arm in arms {
+            let MatchArm {
+                pattern,
+                guard,
+                body,
+                ..
+            } = arm;
+            let mut conditions = vec![];
+            let mut bindings = vec![];
+            self.lower_pattern(pattern, subject.clone(), &mut conditions, &mut bindings);
+            self.code.line();
+            let mut conditions = conditions.into_iter();
+            if let Some(first) = conditions.next() {
+                let condition =
+                    conditions.fold(first, |left, right| Bop::new(left, Operator::And, right));
+                self.code.write("if ");
+                self.compile_expr(condition)?;
+                self.code.write(" then").push();
+            } else {
+                self.code.write("do").push();
+            }
+            for (name, value) in bindings {
+                self.compile_statement(
+                    Let::new(name, DefModifiers::new(), value).into_statement(),
+                )?;
+            }
+            if let Some(guard) = &guard {
+                self.code.line().write("if ");
+                self.compile_expr(guard.clone())?;
+                self.code.write(" then").push();
+            }
+            let returns = Self::ends_in_return(&body, &sink);
+            self.compile_branch(*body, sink.clone())?;
+            if !returns {
+                self.code.line().write(format!("goto match_end_{id};"));
+            }
+            if guard.is_some() {
+                self.code.pop().line().write("end");
+            }
+            self.code.pop().line().write("end");
+        }
+        Ok(())
+    }
+    /// Matches jump past the remaining arms with a `goto`, so `return`,
+    /// `break` and `skip` inside arms keep working.
+    fn compile_match(&mut self, value: Match, sink: Sink) -> Result {
+        let Match { subject, arms, .. } = value;
+        self.match_count += 1;
+        let id = self.match_count;
+        self.code
+            .write("do")
+            .push()
+            .line()
+            .write("local __match_subject__ = ");
+        self.compile_expr(*subject)?;
+        self.code.write(";");
+        self.compile_match_arms(id, arms, sink)?;
         self.code
             .line()
-            .write("::loop_end::")
+            .write(format!("::match_end_{id}::"))
             .pop()
             .line()
             .write("end");
         Ok(())
     }
-    fn compile_loop(&mut self, stmt: Loop) -> Result {
-        self.code.write("while true do").push();
-        self.compile_program(stmt.body)?;
+    fn compile_if_expr(&mut self, value: IfExpr, sink: Sink) -> Result {
+        let IfExpr {
+            condition,
+            body,
+            else_block,
+            ..
+        } = value;
+        self.code.write("if ");
+        self.compile_expr(*condition)?;
+        self.code.write(" then").push();
+        self.compile_block(body, sink.clone())?;
+        let mut else_block = else_block;
+        while let Some(branch) = else_block.take() {
+            match *branch {
+                Expr::IfExpr(IfExpr {
+                    condition,
+                    body,
+                    else_block: next,
+                    ..
+                }) => {
+                    self.code.pop().line().write("elseif ");
+                    self.compile_expr(*condition)?;
+                    self.code.write(" then").push();
+                    self.compile_block(body, sink.clone())?;
+                    else_block = next;
+                }
+                branch if Self::is_discarded_unit(&branch, &sink) => (),
+                branch => {
+                    self.code.pop().line().write("else").push();
+                    self.compile_branch(branch, sink.clone())?;
+                }
+            }
+        }
+        self.code.pop().line().write("end");
+        Ok(())
+    }
+    fn compile_block(&mut self, block: Block, sink: Sink) -> Result {
+        let Block {
+            mut body, value, ..
+        } = block;
+        // Deferred code runs after the value is given, see `compile_defer`.
+        let value = match value {
+            Some(value) if body.iter().any(|stmt| matches!(stmt, Statement::Defer(_))) => {
+                body.push(match sink.clone() {
+                    Sink::Discard => Statement::Expr(*value),
+                    Sink::Return => Return::new(*value),
+                    Sink::Assign(target) => Assignment::new(target, None, *value).into_statement(),
+                });
+                None
+            }
+            value => value,
+        };
+        self.compile_program(body)?;
+        if let Some(value) = value.filter(|value| !Self::is_discarded_unit(value, &sink)) {
+            self.code.line();
+            self.compile_value(*value, sink)?;
+        }
+        Ok(())
+    }
+    /// Branches are already scoped by the enclosing Lua block, so blocks are
+    /// inlined there.
+    fn compile_branch(&mut self, expr: Expr, sink: Sink) -> Result {
+        match expr {
+            Expr::Block(block) => self.compile_block(block, sink),
+            expr => {
+                self.code.line();
+                self.compile_value(expr, sink)
+            }
+        }
+    }
+    /// Unit has no effects, and Lua rejects it as a statement.
+    fn is_discarded_unit(expr: &Expr, sink: &Sink) -> bool {
+        matches!((expr, sink), (Expr::TupleLiteral(unit), Sink::Discard) if unit.is_unit())
+    }
+    fn is_block_like(expr: &Expr) -> bool {
+        matches!(
+            expr,
+            Expr::Block(_)
+                | Expr::IfExpr(_)
+                | Expr::Match(_)
+                | Expr::ArrayComprehension(_)
+                | Expr::MapComprehension(_)
+        )
+    }
+    /// Whether the last Lua statement emitted for `expr` is a `return`, after
+    /// which no other statement can follow. Inside `try` bodies `break` and
+    /// `skip` are emitted as returns too.
+    fn ends_in_return(expr: &Expr, sink: &Sink) -> bool {
+        match expr {
+            Expr::Block(Block {
+                value: Some(value), ..
+            }) => Self::ends_in_return(value, sink),
+            Expr::Block(Block { body, .. }) => matches!(
+                body.last(),
+                Some(Statement::Return(_) | Statement::Break(_) | Statement::Skip(_))
+            ),
+            Expr::IfExpr(_) | Expr::Match(_) => false,
+            _ => matches!(sink, Sink::Return),
+        }
+    }
+    /// Compiles `expr` as a statement that sends its value to `sink`. Block
+    /// like expressions are lowered to plain Lua control flow this way, with
+    /// their value hoisted to the sink instead of wrapped in a function.
+    fn compile_value(&mut self, expr: Expr, sink: Sink) -> Result {
+        match expr {
+            Expr::Block(block) => {
+                self.code.write("do").push();
+                self.compile_block(block, sink)?;
+                self.code.pop().line().write("end");
+            }
+            Expr::IfExpr(value) => self.compile_if_expr(value, sink)?,
+            Expr::Match(value) => self.compile_match(value, sink)?,
+            Expr::ArrayComprehension(value) => {
+                let block = self.lower_array_comprehension(value);
+                self.compile_value(block, sink)?
+            }
+            Expr::MapComprehension(value) => {
+                let block = self.lower_map_comprehension(value);
+                self.compile_value(block, sink)?
+            }
+            value if Self::is_discarded_unit(&value, &sink) => (),
+            value => match sink {
+                Sink::Discard => {
+                    self.compile_expr(value)?;
+                    self.code.write(";");
+                }
+                Sink::Return => self.compile_return(Return::new(value).unwrap_return())?,
+                Sink::Assign(target) => {
+                    self.compile_assignment(Assignment::new(target, None, value))?
+                }
+            },
+        }
+        Ok(())
+    }
+    /// `[value for x in xs if cond]` is lowered to a block filling a table
+    /// from plain `for` loops, so the usual loop optimizations apply. The
+    /// length is kept in a local instead of being taken on every insertion.
+    fn lower_array_comprehension(&mut self, comprehension: ArrayComprehension) -> Expr {
+        let ArrayComprehension { value, loops, span } = comprehension;
+        self.comprehension_count += 1;
+        let id = self.comprehension_count;
+        let out = Identifier::new(format!("__comprehension_{id}__"), false);
+        let len = Identifier::new(format!("__comprehension_{id}_len__"), false);
+        let insert = vec![
+            Assignment::new(
+                len.clone().unwrap_identifier().into_assignmenttarget(),
+                Some(Operator::Add),
+                1.as_expr(),
+            )
+            .into_statement(),
+            Assignment::new(
+                out.clone()
+                    .array_access(len.clone())
+                    .unwrap_arrayaccess()
+                    .into_assignmenttarget(),
+                None,
+                *value,
+            )
+            .into_statement(),
+        ];
+        let body = vec![
+            Let::new(
+                out.clone().unwrap_identifier(),
+                DefModifiers::new(),
+                ArrayLiteral {
+                    values: vec![],
+                    span,
+                }
+                .into_expr(),
+            )
+            .into_statement(),
+            Let::new(len.unwrap_identifier(), DefModifiers::new(), 0.as_expr()).into_statement(),
+            Self::comprehension_loops(loops, insert),
+        ];
+        Block::new(body, Some(out), span).into_expr()
+    }
+    /// `'{ key: value for ... }`, lowered like array comprehensions.
+    fn lower_map_comprehension(&mut self, comprehension: MapComprehension) -> Expr {
+        let MapComprehension {
+            key,
+            value,
+            loops,
+            span,
+        } = comprehension;
+        self.comprehension_count += 1;
+        let out = Identifier::new(
+            format!("__comprehension_{}__", self.comprehension_count),
+            false,
+        );
+        let insert = vec![
+            Assignment::new(
+                out.clone()
+                    .array_access(*key)
+                    .unwrap_arrayaccess()
+                    .into_assignmenttarget(),
+                None,
+                *value,
+            )
+            .into_statement(),
+        ];
+        let body = vec![
+            Let::new(
+                out.clone().unwrap_identifier(),
+                DefModifiers::new(),
+                MapLiteral {
+                    entries: vec![],
+                    span,
+                }
+                .into_expr(),
+            )
+            .into_statement(),
+            Self::comprehension_loops(loops, insert),
+        ];
+        Block::new(body, Some(out), span).into_expr()
+    }
+    /// Comprehensions iterate ranges and the results of calls as `for` does,
+    /// any other expression is a collection whose values are read through
+    /// `ipairs` and destructured by the pattern, whatever its shape. Entries
+    /// of maps are read through an explicit `pairs(m)` call.
+    fn comprehension_source(assignment: Destructure, expr: Expr) -> (Destructure, Expr) {
+        match expr {
+            Expr::Call(_)
+            | Expr::Bop(Bop {
+                op: Operator::Range,
+                ..
+            }) => (assignment, expr),
+            collection => {
+                let value = match assignment {
+                    Destructure::Identifier(identifier) => DestructureEntry::Identifier(identifier),
+                    Destructure::Array(items) => DestructureEntry::Array(items),
+                    Destructure::Map(items) => DestructureEntry::Map(items),
+                    Destructure::Tuple(items) => DestructureEntry::Tuple(items),
+                };
+                let index = Identifier::new("_", false).unwrap_identifier();
+                let assignment =
+                    Destructure::Tuple(vec![DestructureEntry::Identifier(index), value]);
+                let span = collection.span();
+                let ipairs = Call::new(Identifier::new("ipairs", false), vec![collection], false)
+                    .with_span(span);
+                (assignment, ipairs)
+            }
+        }
+    }
+    /// Nests the loops, the first one outermost, around the insertion.
+    fn comprehension_loops(loops: Vec<ComprehensionLoop>, insert: Vec<Statement>) -> Statement {
+        let body = loops
+            .into_iter()
+            .rev()
+            .fold(insert, |body, comprehension_loop| {
+                let ComprehensionLoop {
+                    assignment,
+                    expr,
+                    condition,
+                    span,
+                } = comprehension_loop;
+                let body = match condition {
+                    Some(condition) => vec![IfStatement::new(*condition, body, vec![], None)],
+                    None => body,
+                };
+                let (assignment, expr) = Self::comprehension_source(assignment, *expr);
+                vec![For::new(assignment, expr, body).with_span(span)]
+            });
+        body.into_iter()
+            .next()
+            .expect("Comprehensions have at least one loop")
+    }
+    /// Block like expressions in any other position are wrapped in a function,
+    /// so they can't jump out of the enclosing one.
+    fn compile_block_expr(&mut self, mut expr: Expr) -> Result {
+        if let Err((keyword, span)) = JumpFinder::default().visit_expr(&mut expr) {
+            return Err(CompilerError::SyntaxError(Box::new(Diagnostic::new(
+                format!(
+                    "`{keyword}` can't be used in an expression in this position, bind the expression with `let` first"
+                ),
+                span,
+            ))));
+        }
+        self.code.write("(function()").push().line();
+        self.scopes.push(Scope::default());
+        let result = self.compile_value(expr, Sink::Return);
+        self.scopes.pop();
+        result?;
+        self.code.pop().line().write("end)()");
+        Ok(())
+    }
+    fn compile_method(&mut self, owner: &Identifier, method: Fn) -> Result {
+        let Fn {
+            name,
+            modifiers,
+            annotations,
+            arguments,
+            body,
+            ..
+        } = method;
+        self.code.line().write("function ");
+        self.compile_identifier(owner.clone())?;
+        if modifiers.is_static() {
+            self.code.write(".");
+        } else {
+            self.code.write(":");
+        }
+        self.compile_identifier(name.clone())?;
+        self.code.write("(");
+        self.compile_params(&arguments)?;
+        self.code.write(")").push();
+        let body = Self::into_task(&modifiers, body);
+        let body = Self::compile_param_initializers(arguments, body)?;
+        self.compile_function_body(body)?;
+        self.code.pop().line().write("end");
+        let target = owner.clone().into_expr().add_member(name).unwrap_member();
+        self.compile_decorators(&annotations.decorators, target.into_assignmenttarget())
+    }
+    /// Traits are plain tables holding the default methods, which classes copy
+    /// over. Required methods only exist at compile time.
+    fn compile_trait_def(&mut self, trait_def: TraitDef) -> Result {
+        self.traits
+            .insert(trait_def.name.value.clone(), trait_def.clone());
+        let TraitDef {
+            name,
+            fields,
+            modifiers,
+            annotations,
+            ..
+        } = trait_def;
+        self.process_pub_symbol(&modifiers)?;
+        self.compile_identifier(name.clone())?;
         self.code
+            .write(" = {};")
             .line()
-            .write("::loop_end::")
-            .pop()
+            .write("do")
+            .push()
             .line()
-            .write("end");
+            .write("local Self = ");
+        self.compile_identifier(name.clone())?;
+        self.code.write(";");
+        for field in fields {
+            if let TraitField::Fn(method) = field {
+                self.compile_method(&name, method)?;
+            }
+        }
+        self.compile_decorators(
+            &annotations.decorators,
+            AssignmentTarget::Identifier(name.clone()),
+        )?;
+        self.code.line();
+        self.export_symbol(&modifiers, name)?;
+        self.code.pop().line().write("end");
         Ok(())
     }
     fn compile_class_def(&mut self, class_def: ClassDef) -> Result {
         let ClassDef {
             name,
             parent,
+            traits,
             fields,
             modifiers,
+            annotations,
+            span,
+            ..
         } = class_def;
         // Declare the class table
         self.process_pub_symbol(&modifiers)?;
@@ -823,37 +2156,82 @@ impl LuaCompiler {
             })
             .collect::<Vec<_>>();
         // Expand fields earlier, so important methametods take over precedence (Eg: you declare fn __meta__() or smth).
+        let mut available = methods
+            .iter()
+            .map(|method| method.name.value.clone())
+            .collect::<Vec<_>>();
         for method in methods {
-            let Fn {
-                name: method,
-                modifiers,
-                arguments,
-                body,
-            } = method;
-            self.code.line().write("function ");
+            self.compile_method(&name, method)?;
+        }
+        // Mix in the trait defaults, without overriding the class' own methods.
+        for trait_name in traits.iter() {
+            self.code.line().write("for key, value in pairs(");
+            self.compile_identifier(trait_name.clone())?;
+            self.code.write(") do").push().line().write("if rawget(");
             self.compile_identifier(name.clone())?;
-            if modifiers.is_static() {
-                self.code.write(".");
-            } else {
-                self.code.write(":");
+            self.code.write(", key) == nil then").push().line();
+            self.compile_identifier(name.clone())?;
+            self.code
+                .write("[key] = value;")
+                .pop()
+                .line()
+                .write("end")
+                .pop()
+                .line()
+                .write("end");
+            if let Some(trait_def) = self.traits.get(&trait_name.value) {
+                available.extend(trait_def.default_methods().map(|m| m.name.value.clone()));
             }
-            self.compile_identifier(method)?;
-            self.code.write("(");
-            let mut iter = arguments.clone().into_iter();
-            if let Some(param) = iter.next() {
-                let Param { name, .. } = param;
-                self.compile_identifier(name)?;
+        }
+        let inherited = match &parent {
+            Some(parent) => self.classes.get(&parent.value).cloned(),
+            None => Some(vec![]),
+        };
+        // Required methods can only be checked if the whole hierarchy is known.
+        if let Some(inherited) = inherited {
+            available.extend(inherited);
+            for trait_name in traits.iter() {
+                let Some(trait_def) = self.traits.get(&trait_name.value) else {
+                    continue;
+                };
+                if let Some(missing) = trait_def
+                    .required_methods()
+                    .find(|required| !available.contains(&required.name.value))
+                {
+                    return Err(CompilerError::TypeError(Box::new(Diagnostic::new(
+                        format!(
+                            "class `{}` does not implement `{}`, required by trait `{}`",
+                            name.value, missing.name.value, trait_name.value
+                        ),
+                        span,
+                    ))));
+                }
             }
-            for param in iter {
-                let Param { name, .. } = param;
-                self.code.write(", ");
-                self.compile_identifier(name)?;
+            self.classes.insert(name.value.clone(), available);
+        }
+        if !traits.is_empty() {
+            self.code.line();
+            self.compile_identifier(name.clone())?;
+            self.code.write(".__traits__ = ");
+            if parent.is_some() {
+                self.code.write("setmetatable(");
             }
-            self.code.write(")").push();
-            let body = body;
-            let body = Self::compile_param_initializers(arguments, body)?;
-            self.compile_program(body)?;
-            self.code.pop().line().write("end");
+            self.code.write("{ ");
+            for (i, trait_name) in traits.into_iter().enumerate() {
+                if i > 0 {
+                    self.code.write(", ");
+                }
+                self.code.write("[");
+                self.compile_identifier(trait_name)?;
+                self.code.write("] = true");
+            }
+            self.code.write(" }");
+            if let Some(parent) = &parent {
+                self.code.write(", { __index = ");
+                self.compile_identifier(parent.clone())?;
+                self.code.write(".__traits__ })");
+            }
+            self.code.write(";");
         }
         // Build the metatable.
         self.code.line();
@@ -877,7 +2255,7 @@ impl LuaCompiler {
             vec![],
             None,
         )];
-        if let Some(ident) = parent {
+        if let Some(ident) = &parent {
             let access = name
                 .clone()
                 .into_expr()
@@ -912,58 +2290,63 @@ impl LuaCompiler {
                     .into_mapkey(),
                 LambdaExpr::new(
                     vec![
-                        Param {
-                            name: Identifier::new("self", false).unwrap_identifier(),
-                            type_def: None,
-                            init: None,
-                        },
-                        Param {
-                            name: Identifier::new("key", false).unwrap_identifier(),
-                            type_def: None,
-                            init: None,
-                        },
+                        Param::new(Identifier::new("self", false).unwrap_identifier()),
+                        Param::new(Identifier::new("key", false).unwrap_identifier()),
                     ],
                     index_body,
                 ),
             )],
+            span: Span::default(),
         })?;
         self.code.write(";").line();
+        // Linked for class patterns, see [`INSTANCE_OF_RUNTIME`].
+        if let Some(parent) = parent {
+            self.compile_identifier(name.clone())?;
+            self.code.write(".__meta__.__parent__ = ");
+            self.compile_identifier(parent)?;
+            self.code.write(".__meta__;").line();
+        }
         // Set metatable for the class object:
-        let mut ctor_body = vec![
-            IfStatement {
-                condition: Box::new(
-                    Bop {
-                        left: Box::new(Identifier::new("values", false)),
-                        op: Operator::Eq,
-                        right: Box::new(TupleLiteral::unit().into_expr()),
+        let mut ctor_body = vec![IfStatement::new(
+            Bop::new(
+                Identifier::new("values", false),
+                Operator::Eq,
+                TupleLiteral::unit().into_expr(),
+            ),
+            vec![
+                Assignment::new(
+                    AssignmentTarget::Identifier(
+                        Identifier::new("values", false).unwrap_identifier(),
+                    ),
+                    None,
+                    MapLiteral {
+                        entries: vec![],
+                        span: Span::default(),
                     }
                     .into_expr(),
-                ),
-                body: vec![
-                    Assignment {
-                        left: AssignmentTarget::Identifier(
-                            Identifier::new("values", false).unwrap_identifier(),
-                        ),
-                        right: Box::new(MapLiteral { entries: vec![] }.into_expr()),
-                        op: None,
-                    }
-                    .into_statement(),
-                ],
-                else_if_blocks: vec![],
-                else_block: None,
-            }
-            .into_statement(),
-        ];
+                )
+                .into_statement(),
+            ],
+            vec![],
+            None,
+        )];
         // Ctor should initialize the fields first, if any.
         for field in fields {
             let Let {
-                name, initializer, ..
+                name,
+                initializer,
+                annotations,
+                span,
+                ..
             } = field;
-            let init = initializer.unwrap_or(TupleLiteral::unit().into_expr());
+            let init = initializer
+                .map(|init| Self::decorate(&annotations.decorators, init))
+                .unwrap_or(TupleLiteral::unit().into_expr());
             let Destructure::Identifier(left) = name else {
-                return Err(CompilerError::SyntaxError(format!(
-                    "Fields should be declared as names, destructuring assignment is invalid in class field position!"
-                )));
+                return Err(CompilerError::SyntaxError(Box::new(Diagnostic::new(
+                    "Fields should be declared as names, destructuring assignment is invalid in class field position!",
+                    span,
+                ))));
             };
             let assign = Identifier::new("values", false)
                 .add_member(left)
@@ -979,40 +2362,27 @@ impl LuaCompiler {
                 None,
             ));
         }
-        ctor_body.push(
-            Return {
-                value: Box::new(Call::new(
-                    Identifier::new("setmetatable", false),
-                    vec![
-                        Identifier::new("values", false),
-                        Identifier::new("Self", false)
-                            .add_member(Identifier::new("__meta__", false).unwrap_identifier()),
-                    ],
-                    false,
-                )),
-            }
-            .into_statement(),
-        );
+        ctor_body.push(Return::new(Call::new(
+            Identifier::new("setmetatable", false),
+            vec![
+                Identifier::new("values", false),
+                Identifier::new("Self", false)
+                    .add_member(Identifier::new("__meta__", false).unwrap_identifier()),
+            ],
+            false,
+        )));
         let metatable = MapLiteral {
             entries: vec![(
                 MapKey::Identifier(Identifier::new("__call", false).unwrap_identifier()),
-                LambdaExpr {
-                    params: vec![
-                        Param {
-                            name: Identifier::new("Self", false).unwrap_identifier(),
-                            type_def: None,
-                            init: None,
-                        },
-                        Param {
-                            name: Identifier::new("values", false).unwrap_identifier(),
-                            type_def: None,
-                            init: None,
-                        },
+                LambdaExpr::new(
+                    vec![
+                        Param::new(Identifier::new("Self", false).unwrap_identifier()),
+                        Param::new(Identifier::new("values", false).unwrap_identifier()),
                     ],
-                    body: ctor_body,
-                }
-                .into_expr(),
+                    ctor_body,
+                ),
             )],
+            span: Span::default(),
         }
         .into_expr();
         self.compile_call(
@@ -1023,7 +2393,88 @@ impl LuaCompiler {
             )
             .unwrap_call(),
         )?;
-        self.code.write(";").line();
+        self.code.write(";");
+        self.compile_decorators(
+            &annotations.decorators,
+            AssignmentTarget::Identifier(name.clone()),
+        )?;
+        self.code.line();
+        self.export_symbol(&modifiers, name)?;
+        self.code.pop().line().write("end");
+        Ok(())
+    }
+    /// Each variant is a table with a `__tag__` and its own `__meta__`, shared
+    /// by its instances. Variants with payload build instances when called,
+    /// storing the payload positionally like tuples do; unit variants are
+    /// their own (single) instance.
+    fn compile_enum_def(&mut self, enum_def: EnumDef) -> Result {
+        let EnumDef {
+            name,
+            variants,
+            modifiers,
+            annotations,
+            ..
+        } = enum_def;
+        self.process_pub_symbol(&modifiers)?;
+        self.compile_identifier(name.clone())?;
+        self.code.write(" = {};").line().write("do").push();
+        for variant in variants {
+            let EnumVariant {
+                name: variant,
+                fields,
+                ..
+            } = variant;
+            let tag = format!("{:?}", variant.value);
+            self.code.line();
+            self.compile_identifier(name.clone())?;
+            self.code.write(".");
+            self.compile_identifier(variant.clone())?;
+            self.code
+                .write(format!(" = {{ __tag__ = {tag}, __meta__ = {{ __index = "));
+            self.compile_identifier(name.clone())?;
+            self.code.write(" } };").line().write("setmetatable(");
+            self.compile_identifier(name.clone())?;
+            self.code.write(".");
+            self.compile_identifier(variant.clone())?;
+            if fields.is_empty() {
+                self.code.write(", ");
+                self.compile_identifier(name.clone())?;
+                self.code.write(".");
+                self.compile_identifier(variant)?;
+                self.code.write(".__meta__);");
+                continue;
+            }
+            let params = fields.into_iter().map(Param::new).collect::<Vec<_>>();
+            self.code.write(", { __call = function(Self, ");
+            self.compile_params(&params)?;
+            self.code
+                .write(")")
+                .push()
+                .line()
+                .write(format!("return setmetatable({{ __tag__ = {tag}"));
+            // By position for the variant patterns, and by name for the
+            // members and the destructuring patterns.
+            for (i, param) in params.iter().enumerate() {
+                self.code.write(format!(", __{i} = "));
+                self.compile_identifier(Self::param_name(i, param))?;
+            }
+            for (i, param) in params.iter().enumerate() {
+                self.code.write(", ");
+                self.compile_identifier(Self::param_name(i, param))?;
+                self.code.write(" = ");
+                self.compile_identifier(Self::param_name(i, param))?;
+            }
+            self.code
+                .write(" }, Self.__meta__);")
+                .pop()
+                .line()
+                .write("end });");
+        }
+        self.compile_decorators(
+            &annotations.decorators,
+            AssignmentTarget::Identifier(name.clone()),
+        )?;
+        self.code.line();
         self.export_symbol(&modifiers, name)?;
         self.code.pop().line().write("end");
         Ok(())
@@ -1054,41 +2505,86 @@ impl LuaCompiler {
         }
         Ok(())
     }
+    /// Wraps `value` with the decorators, the innermost (last declared) first.
+    fn decorate(decorators: &[Decorator], value: Expr) -> Expr {
+        decorators.iter().rev().fold(value, |value, decorator| {
+            Call::new(decorator.expr.clone(), vec![value], false)
+        })
+    }
+    /// Replaces an already defined symbol by its decorated value.
+    fn compile_decorators(&mut self, decorators: &[Decorator], target: AssignmentTarget) -> Result {
+        if decorators.is_empty() {
+            return Ok(());
+        }
+        let value = Self::decorate(decorators, target.clone().to_expr());
+        self.compile_statement(Assignment::new(target, None, value).into_statement())
+    }
     fn compile_fn(&mut self, fn_def: crate::parsing::ast::Fn) -> Result {
         let crate::parsing::ast::Fn {
             name,
             modifiers,
+            annotations,
             arguments,
             body,
+            ..
         } = fn_def;
         self.process_pub_symbol(&modifiers)?;
         self.code.write("function ");
         self.compile_identifier(name.clone())?;
         self.code.write("(");
-        let mut iter = arguments.clone().into_iter();
-        if let Some(first) = iter.next() {
-            self.compile_identifier(first.name)?;
-        }
-        for item in iter {
-            self.code.write(", ");
-            self.compile_identifier(item.name)?;
-        }
+        self.compile_params(&arguments)?;
         self.code.write(")").push();
-        let body = body;
+        let body = Self::into_task(&modifiers, body);
         let body = Self::compile_param_initializers(arguments, body)?;
-        self.compile_program(body)?;
+        self.compile_function_body(body)?;
         self.code.pop().line().write("end");
+        self.compile_decorators(
+            &annotations.decorators,
+            AssignmentTarget::Identifier(name.clone()),
+        )?;
         self.export_symbol(&modifiers, name)?;
         Ok(())
     }
     fn compile_return(&mut self, return_stmt: Return) -> Result {
-        self.code.write("return ");
-        self.compile_expr(*return_stmt.value)?;
+        if self.scope().deferred {
+            return Err(Self::leaves_deferred("return", return_stmt.span));
+        }
+        let values = match return_stmt.value.map(|value| *value) {
+            Some(value) if Self::is_block_like(&value) => {
+                return self.compile_value(value, Sink::Return);
+            }
+            Some(Expr::TupleLiteral(tuple))
+                if self.options.multiple_returns && !tuple.is_unit() =>
+            {
+                tuple.values
+            }
+            value => value.into_iter().collect(),
+        };
+        let is_protected = match &mut self.scope().protected {
+            Some(signals) => {
+                signals.returns = true;
+                true
+            }
+            None => false,
+        };
+        self.code.write("return");
+        if is_protected {
+            // Packed to be carried through `pcall` as a single value, keeping
+            // every value of calls, see `compile_guarded`.
+            self.code.write(" \"return\", table.pack(");
+            self.compile_expr_list(values)?;
+            self.code.write(");");
+            return Ok(());
+        }
+        if !values.is_empty() {
+            self.code.write(" ");
+            self.compile_expr_list(values)?;
+        }
         self.code.write(";");
         Ok(())
     }
     fn compile_use(&mut self, use_stmt: Use, root: Option<Vec<Identifier>>) -> Result {
-        let Use { path, use_tree } = use_stmt;
+        let Use { path, use_tree, .. } = use_stmt;
         if let Some(tree) = use_tree {
             let root = if let Some(root) = root {
                 root.into_iter().chain(path.into_iter()).collect()
@@ -1108,44 +2604,322 @@ impl LuaCompiler {
             for item in iter {
                 initializer = initializer.add_member(item);
             }
-            self.compile_let(Let {
-                name: Destructure::Identifier(name),
-                type_def: None,
-                initializer: Some(initializer),
-                modifiers: DefModifiers::new(),
-            })?;
+            self.compile_let(Let::new(name, DefModifiers::new(), initializer))?;
             self.code.line();
         }
         Ok(())
     }
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("The chunk scope is never popped")
+    }
+    /// Function bodies get a fresh scope, control flow inside never leaves them.
+    fn compile_function_body(&mut self, body: Vec<Statement>) -> Result {
+        self.scopes.push(Scope::default());
+        let result = self.compile_program(body);
+        self.scopes.pop();
+        result
+    }
+    fn in_loop(
+        &mut self,
+        label: Option<Identifier>,
+        compile: impl FnOnce(&mut Self) -> Result,
+    ) -> Result {
+        self.loop_count += 1;
+        let id = self.loop_count;
+        self.scope().loops.push(LoopFrame {
+            label: label.map(|label| label.value),
+            id,
+            exits: false,
+        });
+        let result = compile(self);
+        let frame = self.scope().loops.pop().expect("Unbalanced loops");
+        result?;
+        if frame.exits {
+            self.code.line().write(format!("::loop_exit_{id}::"));
+        }
+        Ok(())
+    }
+    /// Jumps within the scope are `break` or `goto` a label of the target
+    /// loop, while jumps out of protected bodies are sent as signals.
+    fn compile_jump(&mut self, signal: Signal, label: Option<Identifier>, span: Span) -> Result {
+        let jump = Jump {
+            signal,
+            label: label.as_ref().map(|label| label.value.clone()),
+        };
+        let scope = self.scope();
+        let target = match &jump.label {
+            Some(name) => scope
+                .loops
+                .iter()
+                .rposition(|frame| frame.label.as_ref() == Some(name)),
+            None => scope.loops.len().checked_sub(1),
+        };
+        if let Some(index) = target {
+            let innermost = index + 1 == scope.loops.len();
+            let frame = &mut scope.loops[index];
+            let code = match signal {
+                Signal::Break if innermost => "break;".to_string(),
+                Signal::Break => {
+                    frame.exits = true;
+                    format!("goto loop_exit_{};", frame.id)
+                }
+                Signal::Skip => format!("goto loop_end_{};", frame.id),
+            };
+            self.code.write(code);
+            return Ok(());
+        }
+        if scope.deferred {
+            return Err(Self::leaves_deferred(jump.keyword(), span));
+        }
+        if let Some(label) = label {
+            if !self.reaches_label(&label.value) {
+                return Err(CompilerError::SyntaxError(Box::new(Diagnostic::new(
+                    format!("use of undeclared label `{}`", label.value),
+                    label.span,
+                ))));
+            }
+        }
+        let name = jump.name();
+        match &mut self.scope().protected {
+            Some(signals) => {
+                signals.send(jump);
+                self.code.write(format!("return \"{name}\";"));
+                Ok(())
+            }
+            None => Err(CompilerError::SyntaxError(Box::new(Diagnostic::new(
+                format!("`{}` can only be used inside loops", jump.keyword()),
+                span,
+            )))),
+        }
+    }
+    /// Whether a loop with the label encloses the current scope, without
+    /// crossing a function boundary.
+    fn reaches_label(&self, label: &str) -> bool {
+        for scope in self.scopes.iter().rev() {
+            if scope
+                .loops
+                .iter()
+                .any(|frame| frame.label.as_deref() == Some(label))
+            {
+                return true;
+            }
+            if scope.protected.is_none() {
+                return false;
+            }
+        }
+        false
+    }
+    fn leaves_deferred(name: &str, span: Span) -> CompilerError {
+        CompilerError::SyntaxError(Box::new(Diagnostic::new(
+            format!("`{name}` can't be used to leave a deferred block"),
+            span,
+        )))
+    }
+    /// Compiles the body of a protected function, returning the control flow
+    /// signals it may send.
+    fn compile_protected(
+        &mut self,
+        body: Vec<Statement>,
+    ) -> std::result::Result<Signals, CompilerError> {
+        self.scopes.push(Scope {
+            protected: Some(Signals::default()),
+            ..Default::default()
+        });
+        let result = self.compile_program(body);
+        let scope = self.scopes.pop().expect("Unbalanced scopes");
+        result?;
+        Ok(scope.protected.unwrap_or_default())
+    }
+    /// Both the body and the catch clause run under `pcall`. They leave
+    /// through a `return` with a signal, replayed after the `finally` clause
+    /// together with any error that was not caught.
+    fn compile_try(&mut self, try_stmt: Try) -> Result {
+        let Try {
+            body,
+            catch,
+            finally,
+            ..
+        } = try_stmt;
+        self.compile_guarded(body, catch, |this| match finally {
+            Some(finally) => this.compile_program(finally),
+            None => Ok(()),
+        })
+    }
+    /// Before Lua 5.4, the rest of the scope runs protected as the body of a
+    /// `try` with the deferred code as the `finally` clause, so later defers
+    /// are nested and run first. Lua 5.4 closes `<close>` variables in
+    /// reverse order already, on any exit.
+    fn compile_defer(&mut self, defer: Defer, rest: Vec<Statement>) -> Result {
+        let Defer { body, .. } = defer;
+        if self.options.lua_version == LuaVersion::Lua54 {
+            self.code
+                .write("local __defer__ <close> = setmetatable({}, { __close = function()")
+                .push();
+            self.compile_deferred(body)?;
+            self.code.pop().line().write("end });");
+            return self.compile_program(rest);
+        }
+        self.compile_guarded(rest, None, |this| this.compile_deferred(body))
+    }
+    fn compile_deferred(&mut self, body: Vec<Statement>) -> Result {
+        self.scopes.push(Scope {
+            deferred: true,
+            ..Default::default()
+        });
+        let result = self.compile_program(body);
+        self.scopes.pop();
+        result
+    }
+    /// Compiles the body (and the catch clause) under `pcall`, then the
+    /// finally clause, then replays the error or the signal that left them.
+    fn compile_guarded(
+        &mut self,
+        body: Vec<Statement>,
+        catch: Option<Catch>,
+        finally: impl FnOnce(&mut Self) -> Result,
+    ) -> Result {
+        self.code
+            .write("do")
+            .push()
+            .line()
+            .write("local __try_ok__, __try_result__, __try_value__ = pcall(function()")
+            .push();
+        let mut signals = self.compile_protected(body)?;
+        self.code.pop().line().write("end);");
+        if let Some(Catch { binding, body, .. }) = catch {
+            self.code
+                .line()
+                .write("if not __try_ok__ then")
+                .push()
+                .line()
+                .write("__try_ok__, __try_result__, __try_value__ = pcall(function(");
+            self.compile_identifier(binding)?;
+            self.code.write(")").push();
+            let caught = self.compile_protected(body)?;
+            signals.merge(caught);
+            self.code
+                .pop()
+                .line()
+                .write("end, __try_result__);")
+                .pop()
+                .line()
+                .write("end");
+        }
+        finally(self)?;
+        self.code
+            .line()
+            .write("if not __try_ok__ then")
+            .push()
+            .line()
+            .write("error(__try_result__, 0);")
+            .pop()
+            .line()
+            .write("end");
+        let span = Span::default();
+        let mut replays = vec![];
+        if signals.returns {
+            let value = Identifier::new("__try_value__", false);
+            let unpack = Member::new(
+                Identifier::new("table", false),
+                MemberOp::Static,
+                Identifier::new("unpack", false),
+            );
+            let count = value.clone().add_member(Identifier {
+                value: "n".into(),
+                is_escaped: false,
+                span,
+            });
+            let replay = Return::new(Call::new(unpack, vec![value, 1.as_expr(), count], false));
+            replays.push(("return".to_string(), replay));
+        }
+        for jump in signals.jumps {
+            let name = jump.name();
+            let label = jump.label.map(|value| Identifier {
+                value,
+                is_escaped: false,
+                span,
+            });
+            let replay = match jump.signal {
+                Signal::Break => Statement::Break(Break { label, span }),
+                Signal::Skip => Statement::Skip(Skip { label, span }),
+            };
+            replays.push((name, replay));
+        }
+        for (name, replay) in replays {
+            self.code
+                .line()
+                .write(format!("if __try_result__ == \"{name}\" then"))
+                .push();
+            self.compile_statement(replay)?;
+            self.code.pop().line().write("end");
+        }
+        self.code.pop().line().write("end");
+        Ok(())
+    }
     fn compile_statement(&mut self, stmt: Statement) -> Result {
         self.code.line();
         match stmt {
             Statement::IfStatement(if_statement) => self.compile_if(if_statement)?,
-            Statement::Expr(expr) => {
-                self.compile_expr(expr)?;
-                self.code.write(";");
+            Statement::Expr(expr) => self.compile_value(expr, Sink::Discard)?,
+            Statement::Skip(Skip { label, span }) => {
+                self.compile_jump(Signal::Skip, label, span)?
+            }
+            Statement::Break(Break { label, span }) => {
+                self.compile_jump(Signal::Break, label, span)?
+            }
+            Statement::For(value) => {
+                self.in_loop(value.label.clone(), |this| this.compile_for(value))?
+            }
+            Statement::While(value) => {
+                self.in_loop(value.label.clone(), |this| this.compile_while(value))?
             }
-            Statement::Skip(_) => {
-                self.code.write("goto loop_end;");
+            Statement::Loop(value) => {
+                self.in_loop(value.label.clone(), |this| this.compile_loop(value))?
             }
-            Statement::Break(_) => {
-                self.code.write("break;");
+            Statement::Try(value) => self.compile_try(value)?,
+            Statement::Throw(Throw { value, .. }) => {
+                self.code.write("error(");
+                self.compile_expr(*value)?;
+                self.code.write(");");
             }
-            Statement::For(value) => self.compile_for(value)?,
-            Statement::While(value) => self.compile_while(value)?,
-            Statement::Loop(value) => self.compile_loop(value)?,
+            Statement::Defer(defer) => self.compile_defer(defer, vec![])?,
             Statement::Let(value) => self.compile_let(value)?,
             Statement::Assignment(value) => self.compile_assignment(value)?,
             Statement::ClassDef(class_def) => self.compile_class_def(class_def)?,
+            Statement::EnumDef(enum_def) => self.compile_enum_def(enum_def)?,
+            Statement::TraitDef(trait_def) => self.compile_trait_def(trait_def)?,
             Statement::Fn(fn_def) => self.compile_fn(fn_def)?,
             Statement::Return(return_stmt) => self.compile_return(return_stmt)?,
             Statement::Use(use_stmt) => self.compile_use(use_stmt, None)?,
+            Statement::MacroDef(macro_def) => return Err(Self::unexpanded(macro_def.span)),
+            // Applied by `reassociate`, before code generation.
+            Statement::FixityDecl(_) => (),
         }
         Ok(())
     }
+    /// Macros are expanded away before compiling, see [`expand_macros`].
+    fn unexpanded(span: Span) -> CompilerError {
+        CompilerError::MacroError(Box::new(Diagnostic::new(
+            "macros must be expanded before code generation",
+            span,
+        )))
+    }
+    /// Null-safe operators are lowered before compiling, see [`lower_optionals`].
+    fn unlowered(span: Span) -> CompilerError {
+        CompilerError::SyntaxError(Box::new(Diagnostic::new(
+            "null-safe operators must be lowered before code generation",
+            span,
+        )))
+    }
     pub fn compile_program(&mut self, ast: Vec<Statement>) -> Result {
-        for stmt in ast {
+        let mut statements = ast.into_iter();
+        while let Some(stmt) = statements.next() {
+            if let Statement::Defer(defer) = stmt {
+                self.code.line();
+                return self.compile_defer(defer, statements.collect());
+            }
             self.compile_statement(stmt)?;
         }
         Ok(())
@@ -1158,7 +2932,11 @@ impl LuaCompiler {
                 Bop::new(
                     target.to_expr().clone(),
                     Operator::Or,
-                    MapLiteral { entries: vec![] }.into_expr(),
+                    MapLiteral {
+                        entries: vec![],
+                        span: Span::default(),
+                    }
+                    .into_expr(),
                 ),
             )
             .into_statement(),
@@ -1180,15 +2958,31 @@ impl Compiler for LuaCompiler {
     ) -> std::result::Result<SaturnusIR, CompilerError> {
         self.module_root_expr = Identifier::new("__modules__", false);
         self.options = options;
+        self.traits.clear();
+        self.classes.clear();
+        self.uses_await = false;
+        self.uses_instance_of = false;
+        self.uses_tuple_values = false;
+        self.scopes = vec![Scope::default()];
+        self.loop_count = 0;
+        self.comprehension_count = 0;
+        self.match_count = 0;
         let location = source.location();
+        let file = source.file();
         let code = source.source();
-        let parser = ProgramParser::new();
-        let ast = match parser.parse(&code) {
-            Ok(ast) => ast,
-            Err(err) => {
-                return Err(CompilerError::ParsingError(format!("{err:?}")));
-            }
-        };
+        let (ast, errors) = parse_program(&code);
+        if !errors.is_empty() {
+            return Err(CompilerError::SyntaxErrors(errors).locate(&code, file));
+        }
+        let ast = expand_macros(ast)
+            .map_err(|err| CompilerError::MacroError(Box::new(err)).locate(&code, file.clone()))?;
+        let mut ast = reassociate(ast, &self.options.fixities)
+            .map_err(|err| CompilerError::SyntaxError(Box::new(err)).locate(&code, file.clone()))?;
+        check_types(&mut ast)
+            .map_err(|err| CompilerError::TypeError(Box::new(err)).locate(&code, file.clone()))?;
+        inline_constants(&mut ast)
+            .map_err(|err| CompilerError::SyntaxError(Box::new(err)).locate(&code, file.clone()))?;
+        lower_optionals(&mut ast);
         if ModuleType::Saturnus == self.options.module_type {
             let modules = Identifier::new("__modules__", false);
             // Initialize modules table
@@ -1196,7 +2990,7 @@ impl Compiler for LuaCompiler {
                 modules.clone().unwrap_identifier(),
             ))?;
             // Initialize this module, if not root.
-            if let Some(path) = location {
+            if let Some(path) = &location {
                 let mut out = self.module_root_expr.clone();
                 for rest in path.iter() {
                     let segment = rest
@@ -1212,14 +3006,25 @@ impl Compiler for LuaCompiler {
                     out = out.add_member(Identifier {
                         value: segment,
                         is_escaped: false,
+                        span: Span::default(),
                     });
                     self.mock_module(AssignmentTarget::Member(out.clone().unwrap_member()))?;
                 }
                 self.module_root_expr = out;
             }
         };
-        self.compile_program(ast)?;
-        let output = std::mem::replace(&mut self.code, IndentedBuilder::new()).unwrap();
+        self.compile_program(ast)
+            .map_err(|err| err.locate(&code, file))?;
+        let mut output = std::mem::replace(&mut self.code, IndentedBuilder::new()).unwrap();
+        if self.uses_await {
+            output = format!("{AWAIT_RUNTIME}\n{output}");
+        }
+        if self.uses_instance_of {
+            output = format!("{INSTANCE_OF_RUNTIME}\n{output}");
+        }
+        if self.uses_tuple_values {
+            output = format!("{TUPLE_VALUES_RUNTIME}\n{output}");
+        }
         Ok(SaturnusIR::from(output))
     }
 }
@@ -1,25 +1,68 @@
 use std::path::PathBuf;
 
-use crate::source::{SaturnusIR, SourceCode};
+use crate::{
+    diagnostics::Diagnostic,
+    parsing::ast::FixityDecl,
+    source::{SaturnusIR, SourceCode},
+};
 
 #[derive(Debug, Clone)]
 pub enum CompilerError {
-    SyntaxError(String),
-    MacroError,
+    SyntaxError(Box<Diagnostic>),
+    /// All the syntax errors found while parsing a source file.
+    SyntaxErrors(Vec<Diagnostic>),
+    /// A macro could not be expanded.
+    MacroError(Box<Diagnostic>),
+    /// The type annotations are not valid.
+    TypeError(Box<Diagnostic>),
     SystemError,
     ParsingError(String),
 }
 impl std::fmt::Display for CompilerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            CompilerError::SyntaxError(cause) => write!(f, "Syntax error: {cause}"),
-            CompilerError::MacroError => write!(f, "Macro expansion error: <not available>"),
+            CompilerError::SyntaxError(diagnostic) => write!(f, "{diagnostic}"),
+            CompilerError::SyntaxErrors(diagnostics) => {
+                for diagnostic in diagnostics {
+                    write!(f, "{diagnostic}\n\n")?;
+                }
+                match diagnostics.len() {
+                    1 => write!(f, "error: aborting due to 1 previous error"),
+                    count => write!(f, "error: aborting due to {count} previous errors"),
+                }
+            }
+            CompilerError::MacroError(diagnostic) => write!(f, "{diagnostic}"),
+            CompilerError::TypeError(diagnostic) => write!(f, "{diagnostic}"),
             CompilerError::SystemError => write!(f, "System error: <not available>"),
             CompilerError::ParsingError(cause) => write!(f, "Parsing error: {cause}"),
         }
     }
 }
 impl std::error::Error for CompilerError {}
+impl CompilerError {
+    /// Resolves the position of the contained diagnostics, if any, against
+    /// the compiled source code.
+    pub fn locate(self, source: &str, location: Option<PathBuf>) -> Self {
+        match self {
+            CompilerError::SyntaxError(diagnostic) => {
+                CompilerError::SyntaxError(Box::new((*diagnostic).locate(source, location)))
+            }
+            CompilerError::MacroError(diagnostic) => {
+                CompilerError::MacroError(Box::new((*diagnostic).locate(source, location)))
+            }
+            CompilerError::TypeError(diagnostic) => {
+                CompilerError::TypeError(Box::new((*diagnostic).locate(source, location)))
+            }
+            CompilerError::SyntaxErrors(diagnostics) => CompilerError::SyntaxErrors(
+                diagnostics
+                    .into_iter()
+                    .map(|diagnostic| diagnostic.locate(source, location.clone()))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum ModuleType {
@@ -33,6 +76,15 @@ pub enum ModuleType {
     Custom(String),
 }
 
+/// Version of the Lua runtime the code is generated for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LuaVersion {
+    #[default]
+    Lua53,
+    /// Enables `<close>` variables, used for `defer`.
+    Lua54,
+}
+
 #[derive(Debug, Clone)]
 pub struct CompilerOptions {
     pub use_std_collections: bool,
@@ -40,6 +92,16 @@ pub struct CompilerOptions {
     pub unit_interop: bool,
     pub module_type: ModuleType,
     pub override_mod_path: Option<PathBuf>,
+    /// Operator fixities imported from other units, see
+    /// `processing::fixity::imported`.
+    pub fixities: Vec<FixityDecl>,
+    /// Returned tuple literals become Lua multiple values, and so do calls
+    /// destructured into tuple patterns, instead of allocating tuple tables.
+    /// Other returned tuples are still tables, which the tuple patterns
+    /// destructure alike. As in Lua, other bindings receiving such a call
+    /// keep its first value alone: `let t = f();` does not rebuild the tuple.
+    pub multiple_returns: bool,
+    pub lua_version: LuaVersion,
 }
 impl Default for CompilerOptions {
     fn default() -> Self {
@@ -49,6 +111,9 @@ impl Default for CompilerOptions {
             unit_interop: true,
             module_type: Default::default(),
             override_mod_path: None,
+            fixities: vec![],
+            multiple_returns: false,
+            lua_version: LuaVersion::Lua53,
         }
     }
 }
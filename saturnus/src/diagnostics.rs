@@ -0,0 +1,268 @@
+use std::{fmt::Display, path::PathBuf};
+
+use lalrpop_util::ParseError;
+
+use crate::parsing::ast::Span;
+
+/// Line and column (both 1-based) of a diagnostic, along with the source line
+/// it points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+    pub excerpt: String,
+    pub width: usize,
+}
+impl SourcePosition {
+    /// Resolves the byte range `span` against `source`.
+    pub fn resolve(source: &str, span: Span) -> Self {
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[start..]
+            .find(['\n', '\r'])
+            .map(|i| start + i)
+            .unwrap_or(source.len());
+        let line = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let end = span.end.clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+        Self {
+            line,
+            column,
+            excerpt: source[line_start..line_end].to_string(),
+            width,
+        }
+    }
+}
+
+/// A compiler message tied to a region of the source code.
+///
+/// Diagnostics are created from a span alone, and later located against the
+/// source text with [`Diagnostic::locate`] so they can be rendered the same way
+/// rustc does: path, line and column, the offending line and carets under it.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub expected: Vec<String>,
+    pub location: Option<PathBuf>,
+    /// Boxed, as only located diagnostics have one and errors are better kept
+    /// small.
+    pub position: Option<Box<SourcePosition>>,
+}
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            expected: vec![],
+            location: None,
+            position: None,
+        }
+    }
+    pub fn with_expected(mut self, expected: Vec<String>) -> Self {
+        for token in expected.iter().map(|token| describe_token(token)) {
+            if !self.expected.contains(&token) {
+                self.expected.push(token);
+            }
+        }
+        self
+    }
+    /// Computes the line, column and excerpt of this diagnostic.
+    pub fn locate(mut self, source: &str, location: Option<PathBuf>) -> Self {
+        self.position = Some(Box::new(SourcePosition::resolve(source, self.span)));
+        self.location = location;
+        self
+    }
+}
+
+/// Turns the raw grammar terminal names reported by LALRPOP into something
+/// readable, eg: `"\"fn\""` into `` `fn` `` and the identifier regex into
+/// `identifier`.
+fn describe_token(token: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = token.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => unescaped.extend(chars.next()),
+            ch => unescaped.push(ch),
+        }
+    }
+    if let Some(pattern) = unescaped
+        .strip_prefix("r#\"")
+        .and_then(|token| token.strip_suffix("\"#"))
+    {
+        return match pattern {
+            "[A-Za-z_][A-Za-z_0-9]*" => "identifier".into(),
+            "`[^`]*`" => "escaped identifier".into(),
+            r"[A-Za-z_][A-Za-z_0-9]*!\(" => "macro call".into(),
+            r"///([^/\n\r][^\n\r]*)?[\n\r]*" => "doc comment".into(),
+            "[0-9][0-9_]*" | "0[xX][0-9a-fA-F_]+" | "0[bB][01_]+" | "0[oO][0-7_]+" => {
+                "integer".into()
+            }
+            pattern if pattern.starts_with("[0-9][0-9_]*(") => "float".into(),
+            r"[\^+-/*.:~%&$!|?><]+" => "operator".into(),
+            r"[\^+-/*+-.:~%&$!|?><]+=" => "assignment operator".into(),
+            pattern if pattern.starts_with('"') => "string".into(),
+            pattern => format!("`{pattern}`"),
+        };
+    }
+    match unescaped
+        .strip_prefix('"')
+        .and_then(|token| token.strip_suffix('"'))
+    {
+        Some(token) => format!("`{token}`"),
+        None => unescaped,
+    }
+}
+
+impl<T: Display> From<ParseError<usize, T, Diagnostic>> for Diagnostic {
+    fn from(value: ParseError<usize, T, Diagnostic>) -> Self {
+        match value {
+            ParseError::InvalidToken { location } => {
+                Diagnostic::new("invalid token", Span::new(location, location + 1))
+            }
+            ParseError::UnrecognizedEof { location, expected } => {
+                Diagnostic::new("unexpected end of file", Span::new(location, location))
+                    .with_expected(expected)
+            }
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => Diagnostic::new(format!("unexpected token `{token}`"), Span::new(start, end))
+                .with_expected(expected),
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => Diagnostic::new(format!("extra token `{token}`"), Span::new(start, end)),
+            ParseError::User { error } => error,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error: {}", self.message)?;
+        let gutter = match self.position.as_deref() {
+            Some(position) => " ".repeat(position.line.to_string().len()),
+            None => String::new(),
+        };
+        if let Some(SourcePosition {
+            line,
+            column,
+            excerpt,
+            width,
+        }) = self.position.as_deref()
+        {
+            let path = self
+                .location
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or("<input>".into());
+            // Tabs are kept so the carets line up with the excerpt above.
+            let padding = excerpt
+                .chars()
+                .take(column - 1)
+                .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            write!(f, "\n{gutter}--> {path}:{line}:{column}")?;
+            write!(f, "\n{gutter} |")?;
+            write!(f, "\n{line} | {excerpt}")?;
+            write!(f, "\n{gutter} | {padding}{}", "^".repeat(*width))?;
+        }
+        if !self.expected.is_empty() {
+            write!(f, "\n{gutter} |")?;
+            write!(
+                f,
+                "\n{gutter} = expected one of: {}",
+                self.expected.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::{
+        backends::LuaCompiler,
+        compiler::{Compiler, CompilerError},
+        parsing::{ast::Span, parse_program},
+        source::SourceCode,
+    };
+
+    #[test]
+    fn syntax_errors_point_to_line_and_column() {
+        let source = "let a = 1;\nlet b = a +;";
+        let mut compiler = LuaCompiler::new();
+        let Err(CompilerError::SyntaxErrors(diagnostics)) =
+            compiler.compile(source, Default::default())
+        else {
+            panic!("Expected a syntax error!");
+        };
+        let diagnostic = &diagnostics[0];
+        let position = diagnostic.position.clone().unwrap();
+        assert_eq!((position.line, position.column), (2, 12));
+        assert_eq!(
+            diagnostic.to_string().lines().take(5).collect::<Vec<_>>(),
+            vec![
+                "error: unexpected token `;`",
+                " --> <input>:2:12",
+                "  |",
+                "2 | let b = a +;",
+                "  |            ^",
+            ]
+        );
+        assert!(diagnostic.expected.contains(&"identifier".to_string()));
+    }
+
+    #[test]
+    fn diagnostics_name_the_source_file() {
+        struct File;
+        impl SourceCode for File {
+            fn source(self) -> String {
+                "let a = ;".into()
+            }
+            fn location(&self) -> Option<PathBuf> {
+                Some("src/main".into())
+            }
+            fn file(&self) -> Option<PathBuf> {
+                Some("src/main.st".into())
+            }
+        }
+        let Err(err) = LuaCompiler::new().compile(File, Default::default()) else {
+            panic!("Expected a syntax error!");
+        };
+        assert_eq!(err.to_string().lines().nth(1), Some(" --> src/main.st:1:9"));
+    }
+
+    #[test]
+    fn parser_recovers_and_reports_every_error() {
+        let source = "let a = ;\nfn f() {\n    let b = 1\n}\nlet c = 3;";
+        let (ast, errors) = parse_program(source);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].span, Span::new(8, 9));
+        assert_eq!(errors[1].span, Span::new(33, 34));
+        // Statements around the broken ones are kept.
+        assert_eq!(ast.len(), 2);
+    }
+
+    #[test]
+    fn out_of_range_literals_are_reported() {
+        let source = "let a = 9223372036854775808;\nlet b = 1e400;";
+        let (_, errors) = parse_program(source);
+        let messages = errors
+            .iter()
+            .map(|e| e.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "integer literal is too large",
+                "float literal is out of range"
+            ]
+        );
+        assert_eq!(errors[0].span, Span::new(8, 27));
+    }
+}
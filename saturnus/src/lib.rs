@@ -6,6 +6,7 @@ use source::{SaturnusIR, SourceCode};
 mod backends;
 pub mod code;
 pub mod compiler;
+pub mod diagnostics;
 pub mod parsing;
 pub mod processing;
 pub mod source;
@@ -49,6 +50,22 @@ impl<'a> Program<'a> {
             .wrap_err("Failed to eval this program chunk")
             .map(Value)
     }
+    /// Executes the chunk as a task, so awaiting an async Rust function
+    /// suspends the script only, instead of blocking the host thread.
+    pub async fn exec_async(self) -> Result<()> {
+        self.0
+            .exec_async()
+            .await
+            .wrap_err("Failed to execute this program chunk")
+    }
+    pub async fn eval_async(self) -> Result<Value> {
+        let value: mlua::Value = self
+            .0
+            .eval_async()
+            .await
+            .wrap_err("Failed to eval this program chunk")?;
+        Ok(Value(value))
+    }
 }
 
 /// # Saturnus runtime
@@ -78,6 +95,14 @@ impl Saturnus {
                 message: "Compilation failed!".into(),
             })
     }
+    /// Expands the macros of the source without compiling it, giving back the
+    /// resulting Saturnus code.
+    pub fn expand_macros(&self, source: impl SourceCode) -> Result<String> {
+        processing::macros::expand_source(source).map_err(|err| RuntimeError {
+            caused_by: Some(Box::new(err)),
+            message: "Macro expansion failed!".into(),
+        })
+    }
     pub fn load<'a>(&self, source: impl SourceCode) -> Result<Program<'a>> {
         let ir = self.compile(source)?;
         self.load_ir(ir)
@@ -88,6 +113,42 @@ impl Saturnus {
     pub fn globals(&self) -> Table {
         Table(self.runtime.globals())
     }
+    /// Exposes a Rust future as a global function. Scripts call it as any
+    /// other function, and it only completes when driven by an async
+    /// executor, see `Program::exec_async(..)`.
+    pub fn register_async_fn<F, A, FR, R>(&self, name: &str, func: F) -> Result<()>
+    where
+        F: Fn(mlua::Lua, A) -> FR + Send + 'static,
+        A: mlua::FromLuaMulti,
+        FR: std::future::Future<Output = mlua::Result<R>> + Send + 'static,
+        R: mlua::IntoLuaMulti,
+    {
+        let func = self
+            .runtime
+            .create_async_function(func)
+            .wrap_err("Failed to create an async function")?;
+        self.runtime
+            .globals()
+            .set(name, func)
+            .wrap_err("Failed to register an async function")
+    }
+    /// Drives a task, returned by calling an `async fn`, to completion.
+    pub async fn await_task(&self, task: Value) -> Result<Value> {
+        self.runtime
+            .load(backends::AWAIT_RUNTIME)
+            .exec()
+            .wrap_err("Failed to load the async runtime")?;
+        let driver: mlua::Function = self
+            .runtime
+            .globals()
+            .get("__await__")
+            .wrap_err("Failed to load the async runtime")?;
+        let value: mlua::Value = driver
+            .call_async(task)
+            .await
+            .wrap_err("Failed to await the task")?;
+        Ok(Value(value))
+    }
 }
 
 trait WrapRtError<T> {
@@ -257,4 +318,33 @@ mod test {
         let i: i32 = out.into();
         assert_eq!(i, 6i32);
     }
+
+    #[tokio::test]
+    async fn awaits_rust_futures() {
+        let sat = Saturnus::new();
+        sat.register_async_fn("double", |_, x: i64| async move {
+            tokio::task::yield_now().await;
+            Ok(x * 2)
+        })
+        .unwrap();
+        sat.load("direct = await double(2);")
+            .unwrap()
+            .exec_async()
+            .await
+            .unwrap();
+        let direct: i64 = sat.globals().get("direct").unwrap().into();
+        assert_eq!(direct, 4);
+        let task = sat
+            .load(
+                r#"
+            async fn plus_one(x) = await double(x) + 1;
+            return plus_one(direct);"#,
+            )
+            .unwrap()
+            .eval()
+            .unwrap();
+        assert!(task.is_suspend());
+        let out: i64 = sat.await_task(task).await.unwrap().into();
+        assert_eq!(out, 9);
+    }
 }
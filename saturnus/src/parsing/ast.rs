@@ -1,20 +1,91 @@
 use macros::{bitmask_impl, wrapper_enum};
 
+/// Byte range of a node within its source code, `start` inclusive and `end`
+/// exclusive. Nodes synthesized by the compiler carry an empty span.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+    /// Smallest span covering both `self` and `other`.
+    pub fn join(self, other: Span) -> Span {
+        if self.is_empty() {
+            return other;
+        }
+        if other.is_empty() {
+            return self;
+        }
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// Implemented by every AST node, gives back the source range it was parsed from.
+pub trait Spanned {
+    fn span(&self) -> Span;
+}
+
+macro_rules! spanned_structs {
+    ( $( $name:ident ),* ) => {
+        $(
+            impl Spanned for $name {
+                fn span(&self) -> Span {
+                    self.span
+                }
+            }
+        )*
+    };
+}
+
 #[derive(Debug, Clone)]
 pub enum Number {
-    Int(i64),
-    Float(f64),
+    Int(i64, Span),
+    Float(f64, Span),
+}
+impl Spanned for Number {
+    fn span(&self) -> Span {
+        match self {
+            Number::Int(_, span) | Number::Float(_, span) => *span,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SatString {
     pub value: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StringFragment {
+    Literal(String),
+    Expr(Expr),
+}
+
+/// A string literal with embedded expressions, like `"Hello {name}!"`.
+#[derive(Debug, Clone)]
+pub struct InterpolatedString {
+    pub fragments: Vec<StringFragment>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub enum Boolean {
-    True,
-    False,
+    True(Span),
+    False(Span),
+}
+impl Spanned for Boolean {
+    fn span(&self) -> Span {
+        match self {
+            Boolean::True(span) | Boolean::False(span) => *span,
+        }
+    }
 }
 
 #[wrapper_enum]
@@ -28,21 +99,25 @@ pub enum MapKey {
 #[derive(Debug, Clone)]
 pub struct MapLiteral {
     pub entries: Vec<(MapKey, Expr)>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct ArrayLiteral {
     pub values: Vec<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct TupleLiteral {
     pub values: Vec<Expr>,
+    pub span: Span,
 }
 impl TupleLiteral {
     pub fn unit() -> Self {
         Self {
             values: Default::default(),
+            span: Span::default(),
         }
     }
     pub fn is_unit(&self) -> bool {
@@ -50,6 +125,34 @@ impl TupleLiteral {
     }
 }
 
+/// `for x in xs if cond` of a comprehension, binding like a `for` loop.
+/// Further loops are nested inside of it.
+#[derive(Debug, Clone)]
+pub struct ComprehensionLoop {
+    pub assignment: Destructure,
+    pub expr: Box<Expr>,
+    pub condition: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+/// `[value for x in xs if cond]`, collects a value per iteration.
+#[derive(Debug, Clone)]
+pub struct ArrayComprehension {
+    pub value: Box<Expr>,
+    pub loops: Vec<ComprehensionLoop>,
+    pub span: Span,
+}
+
+/// `'{ key: value for (k, v) in pairs(m) }`, unlike in map literals the key
+/// is always an expression.
+#[derive(Debug, Clone)]
+pub struct MapComprehension {
+    pub key: Box<Expr>,
+    pub value: Box<Expr>,
+    pub loops: Vec<ComprehensionLoop>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Operator {
     Add,
@@ -76,6 +179,9 @@ pub enum Operator {
     GtEq,
     Eq,
     Neq,
+    /// `a ?? b`, binding tighter than the comparisons. Lowered before code
+    /// generation, see `processing::optional`.
+    Coalesce,
     Custom(String),
 }
 
@@ -84,13 +190,20 @@ pub struct Bop {
     pub left: Box<Expr>,
     pub op: Operator,
     pub right: Box<Expr>,
+    /// Parenthesized in the source, so it is never re-associated with the
+    /// surrounding operators, see `processing::fixity`.
+    pub is_grouped: bool,
+    pub span: Span,
 }
 impl Bop {
     pub fn new(left: Expr, op: Operator, right: Expr) -> Expr {
+        let span = left.span().join(right.span());
         Expr::Bop(Bop {
             left: Box::new(left),
             op,
             right: Box::new(right),
+            is_grouped: false,
+            span,
         })
     }
 }
@@ -99,11 +212,13 @@ impl Bop {
 pub struct Uop {
     pub op: Operator,
     pub expr: Box<Expr>,
+    pub span: Span,
 }
 impl Uop {
     pub fn new(op: Operator, expr: Expr) -> Expr {
         Self {
             op,
+            span: expr.span(),
             expr: Box::new(expr),
         }
         .into_expr()
@@ -126,16 +241,27 @@ impl AssignmentTarget {
         }
     }
 }
+impl Spanned for AssignmentTarget {
+    fn span(&self) -> Span {
+        match self {
+            AssignmentTarget::Member(member) => member.span,
+            AssignmentTarget::ArrayAccess(array_access) => array_access.span,
+            AssignmentTarget::Identifier(identifier) => identifier.span,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Assignment {
     pub left: AssignmentTarget,
     pub right: Box<Expr>,
     pub op: Option<Operator>,
+    pub span: Span,
 }
 impl Assignment {
     pub fn new(left: AssignmentTarget, op: Option<Operator>, right: Expr) -> Self {
         Self {
+            span: left.span().join(right.span()),
             left,
             right: Box::new(right),
             op,
@@ -148,10 +274,12 @@ pub struct Call {
     pub target: Box<Expr>,
     pub arguments: Vec<Expr>,
     pub is_null_safe: bool,
+    pub span: Span,
 }
 impl Call {
     pub fn new(target: Expr, arguments: Vec<Expr>, is_null_safe: bool) -> Expr {
         Expr::Call(Self {
+            span: target.span(),
             target: Box::new(target),
             arguments,
             is_null_safe,
@@ -164,10 +292,12 @@ pub struct ArrayAccess {
     pub target: Box<Expr>,
     pub arguments: Vec<Expr>,
     pub is_null_safe: bool,
+    pub span: Span,
 }
 impl ArrayAccess {
     pub fn new(target: Expr, arguments: Vec<Expr>, is_null_safe: bool) -> Expr {
         Expr::ArrayAccess(Self {
+            span: target.span(),
             target: Box::new(target),
             arguments,
             is_null_safe,
@@ -175,16 +305,41 @@ impl ArrayAccess {
     }
 }
 
+/// `...expr`, expands a table in place. Only valid as a call argument or
+/// array literal element.
+#[derive(Debug, Clone)]
+pub struct Spread {
+    pub expr: Box<Expr>,
+    pub span: Span,
+}
+
+/// `await expr`, suspends the running task until the awaited one completes.
+#[derive(Debug, Clone)]
+pub struct Await {
+    pub expr: Box<Expr>,
+    pub span: Span,
+}
+impl Await {
+    pub fn new(expr: Expr) -> Expr {
+        Expr::Await(Self {
+            span: expr.span(),
+            expr: Box::new(expr),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Identifier {
     pub value: String,
     pub is_escaped: bool,
+    pub span: Span,
 }
 impl Identifier {
     pub fn new(value: impl Into<String>, is_escaped: bool) -> Expr {
         Expr::Identifier(Self {
             value: value.into(),
             is_escaped,
+            span: Span::default(),
         })
     }
     pub fn is_void(&self) -> bool {
@@ -199,6 +354,32 @@ pub enum Destructure {
     Map(Vec<DestructureEntry>),
     Tuple(Vec<DestructureEntry>),
 }
+impl Destructure {
+    /// Reads a tuple or array literal of names as a pattern, where both
+    /// can't be told apart until the pattern ends, with a trailing spread
+    /// read as the rest. Fails with the span of
+    /// the first expression that is not a pattern.
+    pub fn from_expr(expr: Expr) -> Result<Self, Span> {
+        match DestructureEntry::from_expr(expr)? {
+            DestructureEntry::Identifier(identifier) => Ok(Destructure::Identifier(identifier)),
+            DestructureEntry::Array(items) => Ok(Destructure::Array(items)),
+            DestructureEntry::Tuple(items) => Ok(Destructure::Tuple(items)),
+            _ => unreachable!(),
+        }
+    }
+}
+impl Spanned for Destructure {
+    fn span(&self) -> Span {
+        match self {
+            Destructure::Identifier(identifier) => identifier.span,
+            Destructure::Array(items) | Destructure::Map(items) | Destructure::Tuple(items) => {
+                items
+                    .iter()
+                    .fold(Span::default(), |span, item| span.join(item.span()))
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum DestructureEntry {
@@ -207,6 +388,101 @@ pub enum DestructureEntry {
     Map(Vec<DestructureEntry>),
     Tuple(Vec<DestructureEntry>),
     Aliasing(Identifier, Box<DestructureEntry>),
+    /// `entry = value`, taken when the destructured value is unit.
+    Default(Box<DestructureEntry>, Box<Expr>),
+    /// `...name`, collects what the other entries leave. Always the last
+    /// entry of its pattern.
+    Rest(Identifier),
+}
+impl DestructureEntry {
+    fn from_expr(expr: Expr) -> Result<Self, Span> {
+        let items = |values: Vec<Expr>| {
+            let last = values.len().saturating_sub(1);
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| match value {
+                    Expr::Spread(Spread { expr, .. }) if i == last => match *expr {
+                        Expr::Identifier(identifier) => Ok(DestructureEntry::Rest(identifier)),
+                        other => Err(other.span()),
+                    },
+                    value => DestructureEntry::from_expr(value),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match expr {
+            Expr::Identifier(identifier) => Ok(DestructureEntry::Identifier(identifier)),
+            Expr::TupleLiteral(tuple) if !tuple.is_unit() => {
+                Ok(DestructureEntry::Tuple(items(tuple.values)?))
+            }
+            Expr::ArrayLiteral(array) if !array.values.is_empty() => {
+                Ok(DestructureEntry::Array(items(array.values)?))
+            }
+            other => Err(other.span()),
+        }
+    }
+}
+impl Spanned for DestructureEntry {
+    fn span(&self) -> Span {
+        match self {
+            DestructureEntry::Identifier(identifier) => identifier.span,
+            DestructureEntry::Array(items)
+            | DestructureEntry::Map(items)
+            | DestructureEntry::Tuple(items) => items
+                .iter()
+                .fold(Span::default(), |span, item| span.join(item.span())),
+            DestructureEntry::Aliasing(identifier, entry) => identifier.span.join(entry.span()),
+            DestructureEntry::Default(entry, value) => entry.span().join(value.span()),
+            DestructureEntry::Rest(identifier) => identifier.span,
+        }
+    }
+}
+
+/// Shape tested by a `match` arm. Unlike [`Destructure`], patterns can fail
+/// to match: literals are compared, collections are checked to be tables and
+/// class patterns check the metatable of the subject.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard(Span),
+    Literal(Expr),
+    Binding(Identifier),
+    Array(Vec<Pattern>, Span),
+    Tuple(Vec<Pattern>, Span),
+    Map(Vec<(Identifier, Pattern)>, Span),
+    Class(Identifier, Vec<(Identifier, Pattern)>, Span),
+    /// `Shape::Circle(r)`, positional patterns over the variant payload.
+    Variant(Identifier, Identifier, Vec<Pattern>, Span),
+}
+impl Spanned for Pattern {
+    fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard(span)
+            | Pattern::Array(_, span)
+            | Pattern::Tuple(_, span)
+            | Pattern::Map(_, span)
+            | Pattern::Class(_, _, span)
+            | Pattern::Variant(_, _, _, span) => *span,
+            Pattern::Literal(expr) => expr.span(),
+            Pattern::Binding(identifier) => identifier.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Box<Expr>,
+    pub span: Span,
+}
+
+/// `match subject { pattern if guard => body, ... }`, the first arm whose
+/// pattern matches (and whose guard holds) is taken.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub subject: Box<Expr>,
+    pub arms: Vec<MatchArm>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -222,10 +498,12 @@ pub struct Member {
     pub target: Box<Expr>,
     pub op: MemberOp,
     pub field: Identifier,
+    pub span: Span,
 }
 impl Member {
     pub fn new(target: Expr, op: MemberOp, field: Expr) -> Expr {
         Expr::Member(Self {
+            span: target.span().join(field.span()),
             target: Box::new(target),
             op,
             field: field.unwrap_identifier(),
@@ -236,31 +514,102 @@ impl Member {
 #[derive(Debug, Clone)]
 pub struct LambdaExpr {
     pub params: Vec<Param>,
+    /// `{ a: Int -> Int => a }`
+    pub return_type: Option<TypeDef>,
     pub body: Vec<Statement>,
+    pub span: Span,
 }
 impl LambdaExpr {
     pub fn new(params: Vec<Param>, body: Vec<Statement>) -> Expr {
-        Expr::LambdaExpr(Self { params, body })
+        Expr::LambdaExpr(Self {
+            params,
+            return_type: None,
+            body,
+            span: Span::default(),
+        })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Param {
-    pub name: Identifier,
+    /// A synthetic parameter is destructured into the pattern's names.
+    pub name: Destructure,
     pub type_def: Option<TypeDef>,
     pub init: Option<Expr>,
+    /// `...name`, collects the remaining arguments. Only the last parameter
+    /// can be variadic.
+    pub is_variadic: bool,
+    pub span: Span,
+}
+impl Param {
+    pub fn new(name: Identifier) -> Self {
+        Self {
+            span: name.span,
+            name: Destructure::Identifier(name),
+            type_def: None,
+            init: None,
+            is_variadic: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TypeDef {
     pub name: Identifier,
     pub generic_args: Option<Vec<TypeDef>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct ElseIf {
     pub condition: Box<Expr>,
     pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+/// `{ statements; value }`, a block whose value is its trailing expression.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub body: Vec<Statement>,
+    pub value: Option<Box<Expr>>,
+    pub span: Span,
+}
+impl Block {
+    /// A trailing `if` or `match` statement (or nested block) is the value of
+    /// the block when no other trailing expression is given.
+    pub fn new(mut body: Vec<Statement>, value: Option<Expr>, span: Span) -> Self {
+        let value = value.or_else(|| match body.last() {
+            Some(Statement::Expr(Expr::IfExpr(_) | Expr::Match(_) | Expr::Block(_))) => {
+                body.pop().map(Statement::unwrap_expr)
+            }
+            _ => None,
+        });
+        Self {
+            body,
+            value: value.map(Box::new),
+            span,
+        }
+    }
+    /// Statements of a function body returning the value of the block.
+    pub fn into_returning(self) -> Vec<Statement> {
+        let Block {
+            mut body, value, ..
+        } = self;
+        if let Some(value) = value {
+            body.push(Return::new(*value));
+        }
+        body
+    }
+}
+
+/// Expression form of [`IfStatement`], `else if` chains are nested [`IfExpr`]
+/// in the `else_block`, otherwise it holds a [`Block`].
+#[derive(Debug, Clone)]
+pub struct IfExpr {
+    pub condition: Box<Expr>,
+    pub body: Block,
+    pub else_block: Option<Box<Expr>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -269,6 +618,7 @@ pub struct IfStatement {
     pub body: Vec<Statement>,
     pub else_if_blocks: Vec<ElseIf>,
     pub else_block: Option<Vec<Statement>>,
+    pub span: Span,
 }
 impl IfStatement {
     pub fn new(
@@ -278,6 +628,7 @@ impl IfStatement {
         else_block: Option<Vec<Statement>>,
     ) -> Statement {
         Statement::IfStatement(Self {
+            span: condition.span(),
             condition: Box::new(condition),
             body,
             else_if_blocks,
@@ -291,13 +642,17 @@ pub struct For {
     pub assignment: Destructure,
     pub expr: Box<Expr>,
     pub body: Vec<Statement>,
+    pub label: Option<Identifier>,
+    pub span: Span,
 }
 impl For {
     pub fn new(assignment: Destructure, expr: Expr, body: Vec<Statement>) -> Statement {
         Statement::For(Self {
+            span: assignment.span().join(expr.span()),
             assignment,
             expr: Box::new(expr),
             body,
+            label: None,
         })
     }
 }
@@ -306,12 +661,16 @@ impl For {
 pub struct While {
     pub condition: Box<Expr>,
     pub body: Vec<Statement>,
+    pub label: Option<Identifier>,
+    pub span: Span,
 }
 impl While {
     pub fn new(condition: Expr, body: Vec<Statement>) -> Statement {
         Statement::While(Self {
+            span: condition.span(),
             condition: Box::new(condition),
             body,
+            label: None,
         })
     }
 }
@@ -319,25 +678,175 @@ impl While {
 #[derive(Debug, Clone)]
 pub struct Loop {
     pub body: Vec<Statement>,
+    pub label: Option<Identifier>,
+    pub span: Span,
 }
 impl Loop {
     pub fn new(body: Vec<Statement>) -> Statement {
-        Statement::Loop(Self { body })
+        Statement::Loop(Self {
+            body,
+            label: None,
+            span: Span::default(),
+        })
     }
 }
 
 #[derive(Debug, Clone)]
-#[bitmask_impl("pub", "static", "partial")]
+#[bitmask_impl("pub", "static", "partial", "async", "const")]
 pub struct DefModifiers {
     mask: u8,
 }
 
+/// Runtime decorator, `@memoize`: the decorated value is replaced by the
+/// result of calling the expression with it.
+#[derive(Debug, Clone)]
+pub struct Decorator {
+    pub expr: Expr,
+    pub span: Span,
+}
+
+/// Compile-time attribute, `#[deprecated("use bar")]`: never emitted, only
+/// kept in the tree for later passes (lints, docs, test discovery...).
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: Identifier,
+    pub arguments: Vec<Expr>,
+    pub span: Span,
+}
+
+#[wrapper_enum]
+#[derive(Debug, Clone)]
+pub enum Annotation {
+    Decorator,
+    Attribute,
+}
+
+/// Joins the lines of a `///` doc block, without the leading marker.
+pub fn join_docs(lines: Vec<&str>) -> Option<String> {
+    if lines.is_empty() {
+        return None;
+    }
+    let lines = lines
+        .into_iter()
+        .map(|line| {
+            let line = line.strip_prefix("///").unwrap_or(line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            line.strip_prefix(' ').unwrap_or(line)
+        })
+        .collect::<Vec<_>>();
+    Some(lines.join("\n"))
+}
+
+/// A `///` line or an item of a body, as parsed.
+pub enum DocItem<'a, T> {
+    Doc(&'a str),
+    Item(T),
+}
+
+/// Items which may hold the doc block preceding them.
+pub trait Documented {
+    /// Keeps the docs, or drops them if the item is not a definition.
+    fn set_docs(&mut self, docs: Option<String>);
+}
+
+/// Gives each doc block to the item that follows it, the ones left at the end
+/// of the body are dropped.
+pub fn attach_docs<T: Documented>(items: Vec<DocItem<'_, T>>) -> Vec<T> {
+    let mut lines = vec![];
+    let mut out = vec![];
+    for item in items {
+        match item {
+            DocItem::Doc(line) => lines.push(line),
+            DocItem::Item(mut item) => {
+                item.set_docs(join_docs(std::mem::take(&mut lines)));
+                out.push(item);
+            }
+        }
+    }
+    out
+}
+
+impl<T: Documented> Documented for Option<T> {
+    fn set_docs(&mut self, docs: Option<String>) {
+        if let Some(item) = self {
+            item.set_docs(docs);
+        }
+    }
+}
+
+impl Documented for Statement {
+    fn set_docs(&mut self, docs: Option<String>) {
+        match self {
+            Statement::ClassDef(class) => class.docs = docs,
+            Statement::EnumDef(enum_def) => enum_def.docs = docs,
+            Statement::TraitDef(trait_def) => trait_def.docs = docs,
+            Statement::MacroDef(macro_def) => macro_def.docs = docs,
+            Statement::FixityDecl(fixity) => fixity.docs = docs,
+            Statement::Let(let_def) => let_def.docs = docs,
+            Statement::Fn(fn_def) => fn_def.docs = docs,
+            _ => {}
+        }
+    }
+}
+
+impl Documented for ClassField {
+    fn set_docs(&mut self, docs: Option<String>) {
+        match self {
+            ClassField::Fn(fn_def) => fn_def.docs = docs,
+            ClassField::Let(let_def) => let_def.docs = docs,
+        }
+    }
+}
+
+impl Documented for TraitField {
+    fn set_docs(&mut self, docs: Option<String>) {
+        match self {
+            TraitField::FnSignature(signature) => signature.docs = docs,
+            TraitField::Fn(fn_def) => fn_def.docs = docs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    pub decorators: Vec<Decorator>,
+    pub attributes: Vec<Attribute>,
+}
+impl FromIterator<Annotation> for Annotations {
+    fn from_iter<T: IntoIterator<Item = Annotation>>(iter: T) -> Self {
+        let mut annotations = Self::default();
+        for annotation in iter {
+            match annotation {
+                Annotation::Decorator(decorator) => annotations.decorators.push(decorator),
+                Annotation::Attribute(attribute) => annotations.attributes.push(attribute),
+            }
+        }
+        annotations
+    }
+}
+impl Annotations {
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attr| attr.name.value == name)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.decorators.is_empty() && self.attributes.is_empty()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Fn {
     pub name: Identifier,
+    /// Generic type parameters, `fn first<T>(...)`. Only used by the type
+    /// checker, erased from the output.
+    pub type_params: Vec<Identifier>,
     pub modifiers: DefModifiers,
+    pub docs: Option<String>,
+    pub annotations: Annotations,
     pub arguments: Vec<Param>,
+    /// `fn parse(s: String): Option<Int>`
+    pub return_type: Option<TypeDef>,
     pub body: Vec<Statement>,
+    pub span: Span,
 }
 impl Fn {
     pub fn new(
@@ -347,9 +856,14 @@ impl Fn {
         body: Vec<Statement>,
     ) -> Self {
         Self {
+            span: name.span,
             name,
+            type_params: vec![],
             modifiers,
+            docs: None,
+            annotations: Annotations::default(),
             arguments,
+            return_type: None,
             body,
         }
     }
@@ -361,12 +875,18 @@ pub struct Let {
     pub type_def: Option<TypeDef>,
     pub initializer: Option<Expr>,
     pub modifiers: DefModifiers,
+    pub docs: Option<String>,
+    pub annotations: Annotations,
+    pub span: Span,
 }
 impl Let {
     pub fn new(name: Identifier, modifiers: DefModifiers, init: Expr) -> Self {
         Self {
+            span: name.span.join(init.span()),
             name: Destructure::Identifier(name),
             modifiers,
+            docs: None,
+            annotations: Annotations::default(),
             type_def: None,
             initializer: Some(init),
         }
@@ -376,9 +896,15 @@ impl Let {
 #[derive(Debug, Clone)]
 pub struct ClassDef {
     pub name: Identifier,
+    /// Generic type parameters, `class Box<T>`, in scope for every member.
+    pub type_params: Vec<Identifier>,
     pub parent: Option<Identifier>,
+    pub traits: Vec<Identifier>,
     pub fields: Vec<ClassField>,
     pub modifiers: DefModifiers,
+    pub docs: Option<String>,
+    pub annotations: Annotations,
+    pub span: Span,
 }
 impl ClassDef {
     pub fn new(
@@ -386,14 +912,119 @@ impl ClassDef {
         modifiers: DefModifiers,
         parent: Option<Identifier>,
         fields: Vec<ClassField>,
-    ) -> Statement {
-        Statement::ClassDef(Self {
+    ) -> Self {
+        Self {
+            span: name.span,
             name,
+            type_params: vec![],
             modifiers,
+            docs: None,
+            annotations: Annotations::default(),
             parent,
+            traits: vec![],
             fields,
+        }
+    }
+}
+
+/// Bodyless method declared in a trait, every implementor must define it.
+#[derive(Debug, Clone)]
+pub struct FnSignature {
+    pub name: Identifier,
+    pub modifiers: DefModifiers,
+    pub docs: Option<String>,
+    pub annotations: Annotations,
+    pub arguments: Vec<Param>,
+    pub return_type: Option<TypeDef>,
+    pub span: Span,
+}
+
+#[wrapper_enum]
+#[derive(Debug, Clone)]
+pub enum TraitField {
+    FnSignature,
+    Fn,
+}
+impl Spanned for TraitField {
+    fn span(&self) -> Span {
+        match self {
+            TraitField::FnSignature(signature) => signature.span,
+            TraitField::Fn(fn_def) => fn_def.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraitDef {
+    pub name: Identifier,
+    pub fields: Vec<TraitField>,
+    pub modifiers: DefModifiers,
+    pub docs: Option<String>,
+    pub annotations: Annotations,
+    pub span: Span,
+}
+impl TraitDef {
+    pub fn new(name: Identifier, modifiers: DefModifiers, fields: Vec<TraitField>) -> Self {
+        Self {
+            span: name.span,
+            name,
+            fields,
+            modifiers,
+            docs: None,
+            annotations: Annotations::default(),
+        }
+    }
+    pub fn required_methods(&self) -> impl Iterator<Item = &FnSignature> {
+        self.fields.iter().filter_map(|field| match field {
+            TraitField::FnSignature(signature) => Some(signature),
+            TraitField::Fn(_) => None,
         })
     }
+    pub fn default_methods(&self) -> impl Iterator<Item = &Fn> {
+        self.fields.iter().filter_map(|field| match field {
+            TraitField::Fn(fn_def) => Some(fn_def),
+            TraitField::FnSignature(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumVariant {
+    pub name: Identifier,
+    pub fields: Vec<Identifier>,
+    pub span: Span,
+}
+impl EnumVariant {
+    pub fn arity(&self) -> usize {
+        self.fields.len()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumDef {
+    pub name: Identifier,
+    pub variants: Vec<EnumVariant>,
+    pub modifiers: DefModifiers,
+    pub docs: Option<String>,
+    pub annotations: Annotations,
+    pub span: Span,
+}
+impl EnumDef {
+    pub fn new(name: Identifier, modifiers: DefModifiers, variants: Vec<EnumVariant>) -> Self {
+        Self {
+            span: name.span,
+            name,
+            variants,
+            modifiers,
+            docs: None,
+            annotations: Annotations::default(),
+        }
+    }
+    pub fn variant(&self, name: &str) -> Option<&EnumVariant> {
+        self.variants
+            .iter()
+            .find(|variant| variant.name.value == name)
+    }
 }
 
 #[wrapper_enum]
@@ -402,29 +1033,124 @@ pub enum ClassField {
     Fn,
     Let,
 }
+impl Spanned for ClassField {
+    fn span(&self) -> Span {
+        match self {
+            ClassField::Fn(fn_def) => fn_def.span,
+            ClassField::Let(let_def) => let_def.span,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Return {
-    pub value: Box<Expr>,
+    /// Empty for a bare `return;`.
+    pub value: Option<Box<Expr>>,
+    pub span: Span,
 }
 impl Return {
     pub fn new(expr: Expr) -> Statement {
         Statement::Return(Self {
-            value: Box::new(expr),
+            span: expr.span(),
+            value: Some(Box::new(expr)),
         })
     }
 }
 
+/// `break;`, or `break label;` to leave an outer loop.
+#[derive(Debug, Clone)]
+pub struct Break {
+    pub label: Option<Identifier>,
+    pub span: Span,
+}
+
+/// `skip;`, or `skip label;` to go on with the next iteration of an outer
+/// loop.
 #[derive(Debug, Clone)]
-pub struct Break;
+pub struct Skip {
+    pub label: Option<Identifier>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Catch {
+    pub binding: Identifier,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
 
+/// `try { } catch e { } finally { }`, either clause can be left out but not
+/// both.
 #[derive(Debug, Clone)]
-pub struct Skip;
+pub struct Try {
+    pub body: Vec<Statement>,
+    pub catch: Option<Catch>,
+    pub finally: Option<Vec<Statement>>,
+    pub span: Span,
+}
+
+/// `throw expr;`, the value is raised as is, so tables keep their metatable.
+#[derive(Debug, Clone)]
+pub struct Throw {
+    pub value: Box<Expr>,
+    pub span: Span,
+}
+
+/// `defer { }`, or `defer expr;` with the expression as the only statement.
+/// The body runs when the enclosing block is left, in reverse order of the
+/// declarations, however the block is left.
+#[derive(Debug, Clone)]
+pub struct Defer {
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+/// `macro name!(params) { body }`, expanded away before code generation.
+/// The body is a template: parameters are replaced by the argument fragments
+/// and the names it declares are renamed apart, see `processing::macros`.
+#[derive(Debug, Clone)]
+pub struct MacroDef {
+    pub name: Identifier,
+    pub params: Vec<Identifier>,
+    pub body: Block,
+    pub docs: Option<String>,
+    pub span: Span,
+}
+
+/// `name!(arguments)`, replaced by the expansion of the macro.
+#[derive(Debug, Clone)]
+pub struct MacroCall {
+    pub name: Identifier,
+    pub arguments: Vec<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// Chaining the operator without parentheses is an error.
+    None,
+}
+
+/// `infixr 5 <:>;`, precedence (from 0 to 9, higher binds tighter) and
+/// associativity of custom operators. Public declarations are exported.
+#[derive(Debug, Clone)]
+pub struct FixityDecl {
+    pub associativity: Associativity,
+    pub precedence: u8,
+    pub operators: Vec<Operator>,
+    pub modifiers: DefModifiers,
+    pub docs: Option<String>,
+    pub annotations: Annotations,
+    pub span: Span,
+}
 
 #[derive(Debug, Clone)]
 pub struct Use {
     pub path: Vec<Identifier>,
     pub use_tree: Option<Vec<Use>>,
+    pub span: Span,
 }
 
 #[wrapper_enum]
@@ -438,11 +1164,89 @@ pub enum Expr {
     Number,
     Boolean,
     SatString,
+    InterpolatedString,
     Identifier,
     Member,
     MapLiteral,
     ArrayLiteral,
     TupleLiteral,
+    ArrayComprehension,
+    MapComprehension,
+    Match,
+    IfExpr,
+    Block,
+    Spread,
+    Await,
+    MacroCall,
+}
+impl Spanned for Expr {
+    fn span(&self) -> Span {
+        match self {
+            Expr::Call(value) => value.span,
+            Expr::ArrayAccess(value) => value.span,
+            Expr::Bop(value) => value.span,
+            Expr::Uop(value) => value.span,
+            Expr::LambdaExpr(value) => value.span,
+            Expr::Number(value) => value.span(),
+            Expr::Boolean(value) => value.span(),
+            Expr::SatString(value) => value.span,
+            Expr::InterpolatedString(value) => value.span,
+            Expr::Identifier(value) => value.span,
+            Expr::Member(value) => value.span,
+            Expr::MapLiteral(value) => value.span,
+            Expr::ArrayLiteral(value) => value.span,
+            Expr::TupleLiteral(value) => value.span,
+            Expr::ArrayComprehension(value) => value.span,
+            Expr::MapComprehension(value) => value.span,
+            Expr::Match(value) => value.span,
+            Expr::IfExpr(value) => value.span,
+            Expr::Block(value) => value.span,
+            Expr::Spread(value) => value.span,
+            Expr::Await(value) => value.span,
+            Expr::MacroCall(value) => value.span,
+        }
+    }
+}
+impl Expr {
+    /// Marks a binary expression as parenthesized, see [`Bop::is_grouped`].
+    pub fn grouped(self) -> Self {
+        match self {
+            Expr::Bop(bop) => Expr::Bop(Bop {
+                is_grouped: true,
+                ..bop
+            }),
+            other => other,
+        }
+    }
+    /// Replaces the span of the wrapped node, used by the parser once the
+    /// full extent of the expression is known.
+    pub fn with_span(mut self, span: Span) -> Self {
+        match &mut self {
+            Expr::Call(value) => value.span = span,
+            Expr::ArrayAccess(value) => value.span = span,
+            Expr::Bop(value) => value.span = span,
+            Expr::Uop(value) => value.span = span,
+            Expr::LambdaExpr(value) => value.span = span,
+            Expr::Number(Number::Int(_, value) | Number::Float(_, value)) => *value = span,
+            Expr::Boolean(Boolean::True(value) | Boolean::False(value)) => *value = span,
+            Expr::SatString(value) => value.span = span,
+            Expr::InterpolatedString(value) => value.span = span,
+            Expr::Identifier(value) => value.span = span,
+            Expr::Member(value) => value.span = span,
+            Expr::MapLiteral(value) => value.span = span,
+            Expr::ArrayLiteral(value) => value.span = span,
+            Expr::TupleLiteral(value) => value.span = span,
+            Expr::ArrayComprehension(value) => value.span = span,
+            Expr::MapComprehension(value) => value.span = span,
+            Expr::Match(value) => value.span = span,
+            Expr::IfExpr(value) => value.span = span,
+            Expr::Block(value) => value.span = span,
+            Expr::Spread(value) => value.span = span,
+            Expr::Await(value) => value.span = span,
+            Expr::MacroCall(value) => value.span = span,
+        }
+        self
+    }
 }
 
 #[wrapper_enum]
@@ -451,6 +1255,10 @@ pub enum Statement {
     Use,
     IfStatement,
     ClassDef,
+    EnumDef,
+    TraitDef,
+    MacroDef,
+    FixityDecl,
     Assignment,
     Let,
     Fn,
@@ -459,6 +1267,122 @@ pub enum Statement {
     For,
     Break,
     Skip,
+    Try,
+    Throw,
+    Defer,
     Return,
     Expr,
 }
+impl Spanned for Statement {
+    fn span(&self) -> Span {
+        match self {
+            Statement::Use(value) => value.span,
+            Statement::IfStatement(value) => value.span,
+            Statement::ClassDef(value) => value.span,
+            Statement::EnumDef(value) => value.span,
+            Statement::TraitDef(value) => value.span,
+            Statement::MacroDef(value) => value.span,
+            Statement::FixityDecl(value) => value.span,
+            Statement::Assignment(value) => value.span,
+            Statement::Let(value) => value.span,
+            Statement::Fn(value) => value.span,
+            Statement::Loop(value) => value.span,
+            Statement::While(value) => value.span,
+            Statement::For(value) => value.span,
+            Statement::Break(value) => value.span,
+            Statement::Skip(value) => value.span,
+            Statement::Try(value) => value.span,
+            Statement::Throw(value) => value.span,
+            Statement::Defer(value) => value.span,
+            Statement::Return(value) => value.span,
+            Statement::Expr(value) => value.span(),
+        }
+    }
+}
+impl Statement {
+    /// Sets the label of a loop, other statements are left as is.
+    pub fn with_label(mut self, label: Option<Identifier>) -> Self {
+        match &mut self {
+            Statement::For(value) => value.label = label,
+            Statement::While(value) => value.label = label,
+            Statement::Loop(value) => value.label = label,
+            _ => (),
+        }
+        self
+    }
+    /// Replaces the span of the wrapped node, see [`Expr::with_span`].
+    pub fn with_span(mut self, span: Span) -> Self {
+        match &mut self {
+            Statement::Use(value) => value.span = span,
+            Statement::IfStatement(value) => value.span = span,
+            Statement::ClassDef(value) => value.span = span,
+            Statement::EnumDef(value) => value.span = span,
+            Statement::TraitDef(value) => value.span = span,
+            Statement::MacroDef(value) => value.span = span,
+            Statement::FixityDecl(value) => value.span = span,
+            Statement::Assignment(value) => value.span = span,
+            Statement::Let(value) => value.span = span,
+            Statement::Fn(value) => value.span = span,
+            Statement::Loop(value) => value.span = span,
+            Statement::While(value) => value.span = span,
+            Statement::For(value) => value.span = span,
+            Statement::Break(value) => value.span = span,
+            Statement::Skip(value) => value.span = span,
+            Statement::Try(value) => value.span = span,
+            Statement::Throw(value) => value.span = span,
+            Statement::Defer(value) => value.span = span,
+            Statement::Return(value) => value.span = span,
+            // Expression statements are spanned by their expression.
+            Statement::Expr(_) => (),
+        }
+        self
+    }
+}
+
+spanned_structs!(
+    EnumVariant,
+    Decorator,
+    Attribute,
+    SatString,
+    InterpolatedString,
+    MapLiteral,
+    ArrayLiteral,
+    TupleLiteral,
+    Bop,
+    Uop,
+    Assignment,
+    Call,
+    ArrayAccess,
+    Spread,
+    Await,
+    Identifier,
+    Member,
+    LambdaExpr,
+    Param,
+    TypeDef,
+    Block,
+    IfExpr,
+    ElseIf,
+    IfStatement,
+    For,
+    While,
+    Loop,
+    Fn,
+    Let,
+    ClassDef,
+    EnumDef,
+    TraitDef,
+    FnSignature,
+    MatchArm,
+    Match,
+    Return,
+    Break,
+    Skip,
+    Catch,
+    Try,
+    Throw,
+    MacroDef,
+    MacroCall,
+    FixityDecl,
+    Use
+);
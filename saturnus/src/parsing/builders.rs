@@ -1,6 +1,8 @@
 use crate::parsing::ast::DestructureEntry;
 
-use super::ast::{ArrayAccess, Destructure, Expr, Identifier, Member, MemberOp, Number, SatString};
+use super::ast::{
+    ArrayAccess, Destructure, Expr, Identifier, Member, MemberOp, Number, SatString, Span,
+};
 
 pub trait AddMember {
     fn add_member(self, identifier: Identifier) -> Expr;
@@ -11,6 +13,7 @@ impl AddMember for Expr {
             target: Box::new(self),
             op: MemberOp::Member,
             field: identifier,
+            span: Span::default(),
         })
     }
 }
@@ -24,6 +27,7 @@ impl AddArrayAccess for Expr {
             target: Box::new(self),
             arguments: vec![key],
             is_null_safe: false,
+            span: Span::default(),
         })
     }
 }
@@ -33,12 +37,15 @@ pub trait AsExpr {
 }
 impl AsExpr for i64 {
     fn as_expr(self) -> Expr {
-        Expr::Number(Number::Int(self))
+        Expr::Number(Number::Int(self, Span::default()))
     }
 }
 impl AsExpr for String {
     fn as_expr(self) -> Expr {
-        Expr::SatString(SatString { value: self })
+        Expr::SatString(SatString {
+            value: self,
+            span: Span::default(),
+        })
     }
 }
 
@@ -55,9 +62,11 @@ impl LeafCollector for Destructure {
                     DestructureEntry::Array(items) => collect(items),
                     DestructureEntry::Map(items) => collect(items),
                     DestructureEntry::Tuple(items) => collect(items),
-                    DestructureEntry::Aliasing(_, destructure_entry) => {
+                    DestructureEntry::Aliasing(_, destructure_entry)
+                    | DestructureEntry::Default(destructure_entry, _) => {
                         collect(&vec![(**destructure_entry).clone()])
                     }
+                    DestructureEntry::Rest(identifier) => vec![identifier.clone()],
                 })
                 .flatten()
                 .collect()
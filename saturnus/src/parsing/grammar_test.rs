@@ -1,4 +1,41 @@
-use crate::Saturnus;
+use crate::{
+    Saturnus, Value,
+    backends::LuaCompiler,
+    compiler::{Compiler, CompilerError},
+};
+
+/// Compiles the program, leaving out the module table initialization that
+/// heads every output, and the trailing whitespace of each line.
+fn compile(raw: &'static str) -> String {
+    compile_with(&Saturnus::new(), raw)
+}
+
+/// Same as [`compile`], with the options of the given compiler.
+fn compile_with(c: &Saturnus, raw: &'static str) -> String {
+    let out = c.compile(raw).unwrap().to_string();
+    out.trim()
+        .split("\n")
+        .skip(1)
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Compiles and runs the program, giving back the value it returns as a
+/// string.
+fn run(raw: &'static str) -> String {
+    let c = Saturnus::new();
+    let Value(value) = c.load(raw).unwrap().eval().unwrap();
+    value.to_string().unwrap()
+}
+
+/// Compiles with the Lua backend directly, to inspect the compiler error.
+fn compile_error(raw: &'static str) -> CompilerError {
+    let Err(err) = LuaCompiler::new().compile(raw, Default::default()) else {
+        panic!("expected the compilation to fail");
+    };
+    err
+}
 
 #[test]
 fn test_strings() {
@@ -36,3 +73,856 @@ Multilne :)"; "#;
         r#"local x = [[In saturnus\nStrings should be OK\nto be just\n\nMultilne :)]];"#
     )
 }
+
+#[test]
+fn test_interpolated_strings() {
+    let raw = r#" let x = "Hello {name}, {{escaped}} {count + 1} {items[2].name}"; "#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local x = ("Hello " .. tostring(name) .. ", {escaped} " .. tostring(count + 1) .. " " .. tostring(items[2].name));"#
+    )
+}
+
+#[test]
+fn test_match_expression() {
+    let raw = r#" let x = match v { [a, _] if a > 1 => a, { name } => name, _ => 0 }; "#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local x;
+do
+  local __match_subject__ = v;
+  if type(__match_subject__) == "table" and rawlen(__match_subject__) == 2 then
+    local a = __match_subject__[1];
+    if a > 1 then
+      x = a;
+      goto match_end_1;
+    end
+  end
+  if type(__match_subject__) == "table" and __match_subject__.name ~= nil then
+    local name = __match_subject__.name;
+    x = name;
+    goto match_end_1;
+  end
+  do
+    x = 0;
+    goto match_end_1;
+  end
+  ::match_end_1::
+end"#
+    );
+    let raw = r#"
+class Shape {}
+class Circle: Shape { let r = 1; }
+fn describe(v) = match v {
+    Circle { r } => match r { 1 => "unit circle", _ => "circle" },
+    Shape {} => "shape",
+    _ => "none",
+};
+return "{describe(Circle())} {describe(Circle('{ r: 2 }))} {describe(Shape())} {describe(1)}";
+"#;
+    assert_eq!(run(raw), "unit circle circle shape none");
+    // Tuple patterns only take tuples of their arity.
+    let raw = r#"
+class Dog {}
+fn describe(v) = match v {
+    (x, y) => "pair {x} {y}",
+    (_, _, _) => "triple",
+    _ => "other",
+};
+return "{describe((1, 2))}, {describe((1, 2, 3))}, {describe(Dog())}, {describe('{ a: 1 })}";
+"#;
+    assert_eq!(run(raw), "pair 1 2, triple, other, other");
+}
+
+#[test]
+fn test_keyword_member_names() {
+    let raw = r#"
+let found = string::match("abc", "b");
+let s = "xyz";
+let method = s.match("y");
+let t = '{ with: 1, try: 2 };
+return "{found} {method} {t.with} {t.try}";
+"#;
+    assert_eq!(
+        compile(raw),
+        r#"local found = string.match("abc", "b");
+local s = "xyz";
+local method = s:match("y");
+local t = { with = 1, try = 2 };
+return (tostring(found) .. " " .. tostring(method) .. " " .. tostring(t.with) .. " " .. tostring(t.try));"#
+    );
+    assert_eq!(run(raw), "b y 1 2");
+}
+
+#[test]
+fn test_if_expression() {
+    let raw = r#" print(1 + if c { let a = 2; a } else if d { 3 } else { 4 }); "#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"print(1 + (function()
+  if c then
+    local a = 2;
+    return a;
+  elseif d then
+    return 3;
+  else
+    return 4;
+  end
+end)());"#
+    );
+    // The wrapping function can't be left for the enclosing one.
+    let CompilerError::SyntaxError(diagnostic) =
+        compile_error(r#"fn f(c) { let z = foo(if c { return "early"; } else { "b" }); }"#)
+    else {
+        panic!("expected a syntax error");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "`return` can't be used in an expression in this position, bind the expression with `let` first"
+    );
+    let raw = r#"
+fn f(c) {
+    let z = if c { return "early"; } else { "b" };
+    return z ++ "?";
+}
+return f(true) ++ " " ++ f(false);
+"#;
+    assert_eq!(run(raw), "early b?");
+}
+
+#[test]
+fn test_numeric_literals() {
+    let raw = r#" let x = [0xFF, 0b1010, 0o755, 1_000_000, 1e-9, 2.5E3, 2.0]; "#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local x = { 255, 10, 493, 1000000, 1e-9, 2500.0, 2.0 };"#
+    )
+}
+
+#[test]
+fn test_variadic_params_and_spread() {
+    let raw = r#" fn f(a, ...rest) = g(a, ...rest); "#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local function f(a, ...)
+  local rest = table.pack(...);
+  return g(a, table.unpack(rest, 1, rest.n));
+end"#
+    );
+    let raw = r#"
+fn count(...args) = args.n;
+fn forward(...args) = count(...args);
+fn wrap(...args) = count(0, ...args, 0);
+fn prepend(...args) = [1, ...args];
+let xs = prepend(nil, 2);
+return "{forward(1, nil)} {forward(nil, nil, 3, nil)} {wrap(nil)} {xs[3]}";
+"#;
+    assert_eq!(run(raw), "2 4 3 2");
+}
+
+#[test]
+fn test_decorators_and_attributes() {
+    let raw = r#"
+@memoize
+#[deprecated("use fib2")]
+fn fib(n) = n;
+"#;
+    let (program, errors) = super::parse_program(raw);
+    assert!(errors.is_empty());
+    let Some(super::ast::Statement::Fn(fn_def)) = program.first() else {
+        panic!("expected a function definition");
+    };
+    let deprecated = fn_def.annotations.attribute("deprecated").unwrap();
+    assert_eq!(deprecated.arguments.len(), 1);
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local function fib(n)
+  return n;
+end
+fib = memoize(fib);"#
+    )
+}
+
+#[test]
+fn test_enum_definitions() {
+    let raw = r#" enum Option { Some(value), None } "#;
+    let (program, errors) = super::parse_program(raw);
+    assert!(errors.is_empty());
+    let Some(super::ast::Statement::EnumDef(enum_def)) = program.first() else {
+        panic!("expected an enum definition");
+    };
+    assert_eq!(enum_def.variant("Some").unwrap().arity(), 1);
+    assert_eq!(enum_def.variant("None").unwrap().arity(), 0);
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local Option = {};
+do
+  Option.Some = { __tag__ = "Some", __meta__ = { __index = Option } };
+  setmetatable(Option.Some, { __call = function(Self, value)
+    return setmetatable({ __tag__ = "Some", __0 = value, value = value }, Self.__meta__);
+  end });
+  Option.None = { __tag__ = "None", __meta__ = { __index = Option } };
+  setmetatable(Option.None, Option.None.__meta__);
+
+end"#
+    );
+    let raw = r#"
+enum Shape { Circle(radius), Rect(width, height) }
+let c = Shape::Circle(2);
+let { radius } = c;
+let area = match Shape::Rect(3, 4) {
+    Shape::Rect(w, h) => w * h,
+    _ => 0,
+};
+return "{radius} {c.radius} {Shape::Rect(3, 4).height} {area}";
+"#;
+    assert_eq!(run(raw), "2 2 4 12");
+}
+
+#[test]
+fn test_traits() {
+    let c = Saturnus::new();
+    let missing = r#"
+trait Named { fn name(); fn greet() = "Hi " ++ self.name(); }
+class Foo with Named { }
+"#;
+    let CompilerError::TypeError(diagnostic) = compile_error(missing) else {
+        panic!("expected a type error");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "class `Foo` does not implement `name`, required by trait `Named`"
+    );
+    let raw = r#"
+trait Named { fn name(); fn greet() = "Hi " ++ self.name(); }
+class Foo with Named { fn name() = "foo"; }
+"#;
+    let out = c.compile(raw).unwrap().to_string();
+    assert!(out.contains(
+        r#"  for key, value in pairs(Named) do
+    if rawget(Foo, key) == nil then
+      Foo[key] = value;
+    end
+  end
+  Foo.__traits__ = { [Named] = true };"#
+    ));
+}
+
+#[test]
+fn test_async_await() {
+    let raw = r#" async fn get(url) = await fetch(url); "#;
+    let c = Saturnus::new();
+    let out = c.compile(raw).unwrap().to_string();
+    assert!(out.starts_with("__await__ = __await__ or function(task)"));
+    assert!(out.ends_with(
+        r#"local function get(url)
+  return coroutine.create(function()
+    return __await__(fetch(url));
+  end);
+end"#
+    ));
+}
+
+#[test]
+fn test_try_catch_finally() {
+    let raw = r#"
+fn f() {
+    try {
+        return g();
+    } catch e {
+        throw e;
+    } finally {
+        cleanup();
+    }
+}
+"#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local function f()
+  do
+    local __try_ok__, __try_result__, __try_value__ = pcall(function()
+      return "return", table.pack(g());
+    end);
+    if not __try_ok__ then
+      __try_ok__, __try_result__, __try_value__ = pcall(function(e)
+        error(e);
+      end, __try_result__);
+    end
+    cleanup();
+    if not __try_ok__ then
+      error(__try_result__, 0);
+    end
+    if __try_result__ == "return" then
+      return table.unpack(__try_value__, 1, __try_value__.n);
+    end
+  end
+end"#
+    );
+    let raw = r#"
+fn pair() {
+    try {
+        return string::find("saturnus", "turn");
+    } finally {
+        cleaned = true;
+    }
+}
+return "{pair()} {select(2, pair())} {cleaned}";
+"#;
+    assert_eq!(run(raw), "3 6 true");
+}
+
+#[test]
+fn test_doc_comments() {
+    let raw = r#"
+/// ## Adder
+///
+/// Adds `a` and `b`.
+fn add(a, b) = a + b;
+// Not a doc comment.
+class Foo {
+    /// The value.
+    let value = 1;
+}
+"#;
+    let (program, errors) = super::parse_program(raw);
+    assert!(errors.is_empty());
+    let [
+        super::ast::Statement::Fn(add),
+        super::ast::Statement::ClassDef(foo),
+    ] = &program[..]
+    else {
+        panic!("expected a function and a class");
+    };
+    assert_eq!(add.docs.as_deref(), Some("## Adder\n\nAdds `a` and `b`."));
+    assert_eq!(foo.docs, None);
+    let Some(super::ast::ClassField::Let(value)) = foo.fields.first() else {
+        panic!("expected a field");
+    };
+    assert_eq!(value.docs.as_deref(), Some("The value."));
+    // Anywhere else, doc comments are dropped as plain comments.
+    let raw = r#"
+fn f(x) {
+    /// Not a definition.
+    if x { return 1; }
+    return 2; /// Trailing.
+    /// At the end of the block.
+}
+return f(false);
+"#;
+    assert_eq!(run(raw), "2");
+    let CompilerError::SyntaxErrors(diagnostics) = compile_error("let a = 1;\n)") else {
+        panic!("expected a syntax error");
+    };
+    assert!(diagnostics[0].expected.contains(&"doc comment".to_string()));
+}
+
+#[test]
+fn test_macro_expansion() {
+    let raw = r#"
+macro swap!(a, b) {
+    let tmp = a;
+    a = b;
+    b = tmp;
+}
+macro square!(x) { x * x }
+let tmp = 1;
+let other = 2;
+swap!(tmp, other);
+let s = square!(tmp + 1);
+"#;
+    let expanded = crate::processing::macros::expand_source(raw).unwrap();
+    assert_eq!(
+        expanded,
+        r#"let tmp = 1;
+let other = 2;
+let __tmp_1__ = tmp;
+tmp = other;
+other = __tmp_1__;
+let s = (tmp + 1) * (tmp + 1);"#
+    );
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir.lines().last(),
+        Some("local s = (tmp + 1) * (tmp + 1);")
+    );
+    let Err(crate::compiler::CompilerError::MacroError(diagnostic)) =
+        crate::processing::macros::expand_source("let x = square!(1);")
+    else {
+        panic!("expected a macro error");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "cannot find macro `square!` in this scope"
+    );
+}
+
+#[test]
+fn test_operator_fixity() {
+    let raw = r#"
+infixl 6 <+>;
+infixr 5 <:>;
+let a = x <+> y == z;
+let b = x <:> y <:> z;
+let c = (x <:> y) <:> z;
+"#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local a = __lt_plus_gt__(x, y) == z;
+local b = __lt_double_dot_gt__(x, __lt_double_dot_gt__(y, z));
+local c = __lt_double_dot_gt__(__lt_double_dot_gt__(x, y), z);"#
+    );
+    let CompilerError::SyntaxError(diagnostic) =
+        compile_error("infixl 6 <+>;\ninfixr 6 <->;\nlet a = x <+> y <-> z;")
+    else {
+        panic!("expected a fixity conflict");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "cannot mix `<+>` (`infixl 6`) and `<->` (`infixr 6`) in the same expression, use parentheses to group the operands"
+    );
+    let CompilerError::SyntaxError(diagnostic) = compile_error("infixl 6 <+>;\ninfixr 5 <+>;")
+    else {
+        panic!("expected a fixity conflict");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "the operator `<+>` is declared as `infixr 5 <+>` here, and as `infixl 6 <+>` earlier in this unit"
+    );
+    let CompilerError::SyntaxError(diagnostic) = compile_error("infix 5 ..;") else {
+        panic!("expected a fixity conflict");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "the operator `..` is declared as `infix 5 ..` here, and as `infixl 8 ..` by the standard library"
+    );
+    // `..` keeps binding tighter than the arithmetic operators.
+    assert_eq!(
+        compile("let r = 1..n + 1;"),
+        "local r = __stop_stop__(1, n) + 1;"
+    );
+    let (program, _) = super::parse_program("use ops::{ dummy };\nuse other;");
+    let fixities = crate::processing::fixity::imported(&program, |path| {
+        (path == ["ops"]).then(|| "pub infixr 5 <:>;\ninfixl 9 <+>;".to_owned())
+    });
+    assert_eq!(fixities.len(), 1);
+    let mut c = Saturnus::new();
+    c.options.fixities = fixities;
+    assert_eq!(
+        compile_with(&c, "let b = x <:> y <:> z;"),
+        "local b = __lt_double_dot_gt__(x, __lt_double_dot_gt__(y, z));"
+    );
+}
+
+#[test]
+fn test_generic_type_params() {
+    let raw = r#"
+fn first<T>(xs: Array<T>) = xs[1];
+class Box<T> { let value: T; }
+"#;
+    let (program, errors) = super::parse_program(raw);
+    assert!(errors.is_empty());
+    let [
+        super::ast::Statement::Fn(first),
+        super::ast::Statement::ClassDef(class),
+    ] = &program[..]
+    else {
+        panic!("expected a function and a class");
+    };
+    assert_eq!(first.type_params[0].value, "T");
+    assert_eq!(class.type_params[0].value, "T");
+    let plain_ir = compile("fn first<T>(xs: Array<T>) = xs[1];");
+    assert_eq!(
+        plain_ir,
+        r#"local function first(xs)
+  return xs[1];
+end"#
+    );
+    let CompilerError::TypeError(diagnostic) =
+        compile_error("class Box<T> { fn get(x: T<Int>) = x; }")
+    else {
+        panic!("expected a type error");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "the type parameter `T` can't take generic arguments"
+    );
+}
+
+#[test]
+fn test_return_types_and_typed_lambdas() {
+    let raw = r#"
+fn parse(s: String): Option<Int> = tonumber(s);
+let add = { a: Int, b: Int -> Int => a + b };
+"#;
+    let (program, errors) = super::parse_program(raw);
+    assert!(errors.is_empty());
+    let [
+        super::ast::Statement::Fn(parse),
+        super::ast::Statement::Let(add),
+    ] = &program[..]
+    else {
+        panic!("expected a function and a variable");
+    };
+    let return_type = parse.return_type.as_ref().unwrap();
+    assert_eq!(return_type.name.value, "Option");
+    assert_eq!(
+        return_type.generic_args.as_ref().unwrap()[0].name.value,
+        "Int"
+    );
+    let Some(super::ast::Expr::LambdaExpr(lambda)) = &add.initializer else {
+        panic!("expected a lambda");
+    };
+    assert_eq!(
+        lambda.params[1].type_def.as_ref().unwrap().name.value,
+        "Int"
+    );
+    assert_eq!(lambda.return_type.as_ref().unwrap().name.value, "Int");
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local function parse(s)
+  return tonumber(s);
+end
+local add = function(a, b)
+  return a + b;
+end;"#
+    );
+}
+
+#[test]
+fn test_multiple_returns() {
+    let raw = r#"
+fn divmod(a, b) {
+    if b == 0 { return; }
+    return (a / b, a % b);
+}
+let (q, r) = divmod(7, 2);
+let t = divmod(7, 2);
+"#;
+    let mut c = Saturnus::new();
+    c.options.multiple_returns = true;
+    let out = c.compile(raw).unwrap().to_string();
+    assert!(out.starts_with("__tuple_values__ = __tuple_values__ or function(size, ...)"));
+    assert!(out.ends_with(
+        r#"local function divmod(a, b)
+  if b == 0 then
+    return;
+  end
+  return a / b, __percent__(a, b);
+end
+local q, r = __tuple_values__(2, divmod(7, 2));
+local t = divmod(7, 2);"#
+    ));
+    // Tuples held in variables are returned as a table, destructured alike.
+    let raw = r#"
+fn values() = (1, 2);
+fn pair() {
+    let t = (3, 4);
+    return t;
+}
+fn nested() {
+    let t = ((5, 6), nil);
+    return t;
+}
+let (a, b) = values();
+let (c, d) = pair();
+let ((e, f), g) = nested();
+return "{a} {b} {c} {d} {e} {f} {g}";
+"#;
+    let Value(value) = c.load(raw).unwrap().eval().unwrap();
+    assert_eq!(value.to_string().unwrap(), "1 2 3 4 5 6 nil");
+}
+
+#[test]
+fn test_defer() {
+    let raw = r#"
+fn save(path) {
+    let file = io::open(path, "w");
+    defer file.close();
+    defer {
+        print("saved");
+    }
+    return file.write("data");
+}
+"#;
+    let mut c = Saturnus::new();
+    c.options.lua_version = crate::compiler::LuaVersion::Lua54;
+    let plain_ir = compile_with(&c, raw);
+    assert_eq!(
+        plain_ir,
+        r#"local function save(path)
+  local file = io.open(path, "w");
+  local __defer__ <close> = setmetatable({}, { __close = function()
+    file:close();
+  end });
+  local __defer__ <close> = setmetatable({}, { __close = function()
+    print("saved");
+  end });
+  return file:write("data");
+end"#
+    );
+    let c = Saturnus::new();
+    assert!(c.compile("fn f() { defer { return 1; } }").is_err());
+}
+
+#[test]
+fn test_labeled_loops() {
+    let raw = r#"
+outer: for x in 1..3 {
+    for y in 1..3 {
+        if y == x { skip outer; }
+        if x == 3 { break outer; }
+    }
+}
+"#;
+    let plain_ir = compile(raw);
+    let c = Saturnus::new();
+    assert_eq!(
+        plain_ir,
+        r#"for x = 1, 3 do
+  for y = 1, 3 do
+    if y == x then
+      goto loop_end_1;
+    end
+    if x == 3 then
+      goto loop_exit_1;
+    end
+    ::loop_end_2::
+  end
+  ::loop_end_1::
+end
+::loop_exit_1::"#
+    );
+    assert!(c.compile("outer: loop { loop { break inner; } }").is_err());
+}
+
+#[test]
+fn test_null_safety() {
+    let raw = r#"
+let name = next_item()?.name ?? "none";
+"#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local name;
+do
+  local __optional_2__;
+  do
+    local __optional_1__ = next_item();
+    if __optional_1__ ~= nil then
+      __optional_2__ = __optional_1__.name;
+    else
+      __optional_2__ = nil;
+    end
+  end
+  if __optional_2__ ~= nil then
+    name = __optional_2__;
+  else
+    name = "none";
+  end
+end"#
+    );
+    // `??` binds tighter than the comparisons, as its operands are values.
+    let plain_ir = compile("let same = a == b ?? c and d;");
+    assert_eq!(
+        plain_ir,
+        r#"local same = a == (function()
+  if b ~= nil then
+    return b;
+  else
+    return c;
+  end
+end)() and d;"#
+    );
+    assert_eq!(
+        compile("let x = nil?.y;"),
+        r#"local x;
+do
+  local __optional_1__ = nil;
+  if __optional_1__ ~= nil then
+    x = __optional_1__.y;
+  else
+    x = nil;
+  end
+end"#
+    );
+    let raw = r#"
+let t = nil;
+let a = nil?.x;
+let b = t?.y ?? "none";
+let same = 1 == nil ?? 1;
+let either = nil ?? false or true;
+return "{a} {b} {same} {either} {false ?? 1}";
+"#;
+    assert_eq!(run(raw), "nil none true true false");
+}
+
+#[test]
+fn test_comprehensions() {
+    let raw = r#"
+let xs = [x * 2 for x in 1..5 if x != 2];
+let m = '{ k: v for (k, v) in pairs(xs) };
+"#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local xs;
+do
+  local __comprehension_1__ = {  };
+  local __comprehension_1_len__ = 0;
+  for x = 1, 5 do
+    if x ~= 2 then
+      __comprehension_1_len__ = __comprehension_1_len__ + 1;
+      __comprehension_1__[__comprehension_1_len__] = x * 2;
+    end
+    ::loop_end_1::
+  end
+  xs = __comprehension_1__;
+end
+local m;
+do
+  local __comprehension_2__ = {  };
+  for k, v in pairs(xs) do
+    __comprehension_2__[k] = v;
+    ::loop_end_2::
+  end
+  m = __comprehension_2__;
+end"#
+    );
+    let raw = r#"
+let ys = [y + 1 for y in xs];
+let n = '{ k: v for (k, v) in pairs(m) };
+"#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local ys;
+do
+  local __comprehension_1__ = {  };
+  local __comprehension_1_len__ = 0;
+  for _, y in ipairs(xs) do
+    __comprehension_1_len__ = __comprehension_1_len__ + 1;
+    __comprehension_1__[__comprehension_1_len__] = y + 1;
+    ::loop_end_1::
+  end
+  ys = __comprehension_1__;
+end
+local n;
+do
+  local __comprehension_2__ = {  };
+  for k, v in pairs(m) do
+    __comprehension_2__[k] = v;
+    ::loop_end_2::
+  end
+  n = __comprehension_2__;
+end"#
+    );
+    let raw = r#"
+let xs = [x * 2 for x in [1, 2, 3] if x > 1];
+let m = '{ k: v + 1 for (k, v) in pairs('{ a: 1, b: 2 }) };
+let sums = [a + b for [a, b] in [[1, 2], [3, 4]]];
+let tuples = [a * b for (a, b) in [(1, 2), (3, 4)]];
+let words = [w ++ "!" for w in string::gmatch("hi yo", "%a+")];
+return "{xs[1]} {xs[2]} {m.a} {m.b} {sums[2]} {tuples[1]} {tuples[2]} {words[2]}";
+"#;
+    assert_eq!(run(raw), "4 6 2 3 7 2 12 yo!");
+}
+
+#[test]
+fn test_param_destructuring() {
+    let raw = r#"
+fn area({ w, h } <- '{ w: 1, h: 1 }) { return w * h; }
+let sum = { (k, v) => k + v };
+"#;
+    let plain_ir = compile(raw);
+    let c = Saturnus::new();
+    assert_eq!(
+        plain_ir,
+        r#"local function area(__param_0__)
+  if __param_0__ == nil then
+    __param_0__ = { w = 1, h = 1 };
+  end
+  local w, h;
+  do
+    local __destructure_target__ = __param_0__;
+    w = __destructure_target__.w;
+    h = __destructure_target__.h;
+  end
+  return w * h;
+end
+local sum = function(__param_0__)
+  local k, v;
+  do
+    local __destructure_target__ = __param_0__;
+    k = __destructure_target__.__0;
+    v = __destructure_target__.__1;
+  end
+  return k + v;
+end;"#
+    );
+    assert!(c.compile("let f = { (1, x) => x };").is_err());
+}
+
+#[test]
+fn test_destructure_defaults_and_rest() {
+    let raw = r#"
+let { port = 8080, ...others } = cfg;
+let [head, ...tail] = xs;
+"#;
+    let plain_ir = compile(raw);
+    assert_eq!(
+        plain_ir,
+        r#"local port, others;
+do
+  local __destructure_target__ = cfg;
+  port = __destructure_target__.port;
+  if port == nil then
+    port = 8080;
+  end
+  others = {  };
+  for __key__, __value__ in pairs(__destructure_target__) do
+    if __key__ ~= "port" then
+      others[__key__] = __value__;
+    end
+  end
+end
+local head, tail;
+do
+  local __destructure_target__ = xs;
+  head = __destructure_target__[1];
+  tail = table.move(__destructure_target__, 2, #__destructure_target__, 1, {  });
+end"#
+    );
+}
+
+#[test]
+fn test_constants() {
+    let raw = r#"
+const LIMIT = 10;
+let items = fetch(LIMIT);
+"#;
+    let mut c = Saturnus::new();
+    c.options.lua_version = crate::compiler::LuaVersion::Lua54;
+    let plain_ir = compile_with(&c, raw);
+    assert_eq!(
+        plain_ir,
+        r#"local LIMIT <const> = 10;
+local items = fetch(10);"#
+    );
+    let c = Saturnus::new();
+    assert!(c.compile("const X = 1; X = 2;").is_err());
+    assert!(c.compile("fn f(X) { X = 2; } const X = 1;").is_ok());
+    assert!(
+        c.compile("class A { const M = 1; fn set(self) { self.M = 2; } }")
+            .is_err()
+    );
+}
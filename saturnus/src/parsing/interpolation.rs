@@ -0,0 +1,87 @@
+use crate::diagnostics::Diagnostic;
+
+use super::{
+    ast::{Expr, InterpolatedString, IntoExpr, SatString, Span, StringFragment},
+    grammar::ExprParser,
+};
+
+/// Splits a string literal into its text and `{expr}` fragments.
+///
+/// Braces are escaped by doubling them (`{{` and `}}`). Literals without any
+/// interpolation are given back as plain strings.
+pub fn interpolate(literal: SatString) -> Result<Expr, Diagnostic> {
+    let SatString { value, span } = literal;
+    // Skip the opening quote, so positions map back to the source code.
+    let base = span.start + 1;
+    let mut fragments = vec![];
+    let mut text = String::new();
+    let mut chars = value.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '{' if chars.next_if(|(_, next)| *next == '{').is_some() => text.push('{'),
+            '}' if chars.next_if(|(_, next)| *next == '}').is_some() => text.push('}'),
+            '}' => {
+                return Err(Diagnostic::new(
+                    "unmatched `}` in string, use `}}` to write a brace",
+                    Span::new(base + i, base + i + 1),
+                ));
+            }
+            '{' => {
+                let mut depth = 1;
+                let end = chars.by_ref().find_map(|(j, ch)| {
+                    match ch {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => (),
+                    }
+                    (depth == 0).then_some(j)
+                });
+                let Some(end) = end else {
+                    return Err(Diagnostic::new(
+                        "unterminated interpolation in string, use `{{` to write a brace",
+                        Span::new(base + i, base + i + 1),
+                    ));
+                };
+                let code = &value[i + 1..end];
+                if code.trim().is_empty() {
+                    return Err(Diagnostic::new(
+                        "empty interpolation in string",
+                        Span::new(base + i, base + end + 1),
+                    ));
+                }
+                if !text.is_empty() {
+                    fragments.push(StringFragment::Literal(std::mem::take(&mut text)));
+                }
+                fragments.push(StringFragment::Expr(parse_embedded(code, base + i + 1)?));
+            }
+            // Escape sequences are kept verbatim, as in plain strings.
+            '\\' => {
+                text.push(ch);
+                text.extend(chars.next().map(|(_, next)| next));
+            }
+            ch => text.push(ch),
+        }
+    }
+    if fragments.is_empty() {
+        return Ok(SatString { value: text, span }.into_expr());
+    }
+    if !text.is_empty() {
+        fragments.push(StringFragment::Literal(text));
+    }
+    Ok(InterpolatedString { fragments, span }.into_expr())
+}
+
+/// Parses the code of an interpolation. The code is left-padded up to its
+/// offset, so the spans of the resulting expression point to the original
+/// source code.
+fn parse_embedded(code: &str, offset: usize) -> Result<Expr, Diagnostic> {
+    let padded = format!("{}{code}", " ".repeat(offset));
+    let mut errors = vec![];
+    let expr = ExprParser::new()
+        .parse(&mut errors, &padded)
+        .map_err(Diagnostic::from)?;
+    match errors.into_iter().next() {
+        Some(recovery) => Err(Diagnostic::from(recovery.error)),
+        None => Ok(expr),
+    }
+}
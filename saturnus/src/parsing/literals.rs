@@ -0,0 +1,37 @@
+use crate::diagnostics::Diagnostic;
+
+use super::ast::Span;
+
+/// Parses an integer literal, as in `1_000`, `0xFF`, `0b1010` or `0o755`.
+///
+/// Like in Lua, hexadecimal, binary and octal literals wrap around to negative
+/// numbers when they don't fit in an `i64` but do in 64 bits, so `0xFFFFFFFFFFFFFFFF`
+/// is `-1`. Decimal literals must fit in an `i64`.
+pub fn parse_int(literal: &str, span: Span) -> Result<i64, Diagnostic> {
+    let digits = literal.replace('_', "");
+    let radix = match digits.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0b" | "0B") => 2,
+        Some("0o" | "0O") => 8,
+        _ => 10,
+    };
+    if radix == 10 {
+        return digits
+            .parse::<i64>()
+            .map_err(|_| Diagnostic::new("integer literal is too large", span));
+    }
+    if digits.len() == 2 {
+        return Err(Diagnostic::new("no digits found in integer literal", span));
+    }
+    u64::from_str_radix(&digits[2..], radix)
+        .map(|value| value as i64)
+        .map_err(|_| Diagnostic::new("integer literal is too large", span))
+}
+
+/// Parses a float literal, as in `2.5`, `1e-9` or `2.5E3`.
+pub fn parse_float(literal: &str, span: Span) -> Result<f64, Diagnostic> {
+    match literal.replace('_', "").parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(Diagnostic::new("float literal is out of range", span)),
+    }
+}
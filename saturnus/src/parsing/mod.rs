@@ -1,14 +1,40 @@
 pub mod ast;
 pub mod builders;
+pub mod interpolation;
+pub mod literals;
+pub mod printer;
 
 #[cfg(test)]
 mod grammar_test;
 
 use lalrpop_util::lalrpop_mod;
 
+use crate::diagnostics::Diagnostic;
+
 lalrpop_mod!(
     #[allow(clippy::ptr_arg)]
     #[rustfmt::skip]
     pub grammar,
     "/parsing/grammar.rs"
 );
+
+/// Parses a whole program without stopping at the first syntax error.
+///
+/// Returns whatever statements could be recovered along with every syntax
+/// error found, so an empty error list means the AST is complete. Diagnostics
+/// are not located, see [`Diagnostic::locate`].
+pub fn parse_program(source: &str) -> (Vec<ast::Statement>, Vec<Diagnostic>) {
+    let mut recovered = vec![];
+    let result = grammar::ProgramParser::new().parse(&mut recovered, source);
+    let mut errors = recovered
+        .into_iter()
+        .map(|recovery| Diagnostic::from(recovery.error))
+        .collect::<Vec<_>>();
+    match result {
+        Ok(ast) => (ast, errors),
+        Err(err) => {
+            errors.push(Diagnostic::from(err));
+            (vec![], errors)
+        }
+    }
+}
@@ -0,0 +1,841 @@
+use crate::code::IndentedBuilder;
+
+use super::ast::{
+    Annotations, AssignmentTarget, Associativity, Block, Boolean, ClassField, ComprehensionLoop,
+    DefModifiers, Destructure, DestructureEntry, Expr, Fn, Identifier, IfExpr, Let, MapKey,
+    MemberOp, Number, Operator, Param, Pattern, Return, Statement, StringFragment, TraitField,
+    TypeDef, Use,
+};
+
+/// Prints a program back as Saturnus source code, used to inspect the result
+/// of the passes run before code generation (eg: macro expansion).
+///
+/// The output parses back into an equivalent program, but comments are lost
+/// and block expressions outside of `if` and `match` are printed as called
+/// lambdas, as there is no other way of writing them.
+pub fn print_program(program: &[Statement]) -> String {
+    let mut printer = Printer {
+        code: IndentedBuilder::new(),
+    };
+    for (i, statement) in program.iter().enumerate() {
+        if i > 0 {
+            printer.code.line();
+        }
+        printer.statement(statement);
+    }
+    printer.code.unwrap()
+}
+
+struct Printer {
+    code: IndentedBuilder,
+}
+
+/// Binding power of the binary operators, as laid out by the grammar tiers.
+fn precedence(op: &Operator) -> u8 {
+    match op {
+        Operator::Custom(_) => 0,
+        Operator::And | Operator::Or => 1,
+        Operator::Lt
+        | Operator::LtEq
+        | Operator::Gt
+        | Operator::GtEq
+        | Operator::Eq
+        | Operator::Neq => 2,
+        Operator::Coalesce => 3,
+        Operator::Add | Operator::Sub | Operator::StrCat => 4,
+        Operator::Mul | Operator::Div => 5,
+        Operator::Pow | Operator::Range => 6,
+        _ => 7,
+    }
+}
+
+pub(crate) fn operator(op: &Operator) -> &str {
+    match op {
+        Operator::Add => "+",
+        Operator::Sub => "-",
+        Operator::Mul => "*",
+        Operator::Div => "/",
+        Operator::Pow => "**",
+        Operator::And => "and",
+        Operator::Or => "or",
+        Operator::Not => "not",
+        Operator::BAnd => "&",
+        Operator::BOr => "|",
+        Operator::BXor => "^",
+        Operator::BNot => "~",
+        Operator::LShift => "<<",
+        Operator::LShiftRot => "<<<",
+        Operator::RShift => ">>",
+        Operator::RShiftRot => ">>>",
+        Operator::StrCat => "++",
+        Operator::Range => "..",
+        Operator::Lt => "<",
+        Operator::LtEq => "<=",
+        Operator::Gt => ">",
+        Operator::GtEq => ">=",
+        Operator::Eq => "==",
+        Operator::Neq => "!=",
+        Operator::Coalesce => "??",
+        Operator::Custom(op) => op,
+    }
+}
+
+/// Braces are interpolations in string literals, so they are escaped back.
+fn escape_braces(value: &str) -> String {
+    value.replace('{', "{{").replace('}', "}}")
+}
+
+impl Printer {
+    fn comma<T>(&mut self, items: &[T], mut print: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.code.write(", ");
+            }
+            print(self, item);
+        }
+    }
+    fn identifier(&mut self, identifier: &Identifier) {
+        self.code.write(&identifier.value);
+    }
+    fn docs(&mut self, docs: &Option<String>) {
+        if let Some(docs) = docs {
+            for line in docs.lines() {
+                if line.is_empty() {
+                    self.code.write("///").line();
+                } else {
+                    self.code.write(format!("/// {line}")).line();
+                }
+            }
+        }
+    }
+    fn annotations(&mut self, annotations: &Annotations) {
+        for decorator in annotations.decorators.iter() {
+            self.code.write("@");
+            self.expr(&decorator.expr);
+            self.code.line();
+        }
+        for attribute in annotations.attributes.iter() {
+            self.code.write("#[");
+            self.identifier(&attribute.name);
+            if !attribute.arguments.is_empty() {
+                self.code.write("(");
+                self.comma(&attribute.arguments, Self::expr);
+                self.code.write(")");
+            }
+            self.code.write("]").line();
+        }
+    }
+    fn modifiers(&mut self, modifiers: &DefModifiers) {
+        if modifiers.is_pub() {
+            self.code.write("pub ");
+        }
+        if modifiers.is_static() {
+            self.code.write("static ");
+        }
+        if modifiers.is_partial() {
+            self.code.write("partial ");
+        }
+        if modifiers.is_async() {
+            self.code.write("async ");
+        }
+    }
+    fn type_def(&mut self, type_def: &TypeDef) {
+        self.identifier(&type_def.name);
+        if let Some(args) = &type_def.generic_args {
+            self.code.write("<");
+            self.comma(args, Self::type_def);
+            self.code.write(">");
+        }
+    }
+    fn type_params(&mut self, type_params: &[Identifier]) {
+        if !type_params.is_empty() {
+            self.code.write("<");
+            self.comma(type_params, Self::identifier);
+            self.code.write(">");
+        }
+    }
+    fn return_type(&mut self, return_type: &Option<TypeDef>) {
+        if let Some(return_type) = return_type {
+            self.code.write(": ");
+            self.type_def(return_type);
+        }
+    }
+    fn param(&mut self, param: &Param) {
+        if param.is_variadic {
+            self.code.write("...");
+        }
+        self.destructure(&param.name);
+        if let Some(type_def) = &param.type_def {
+            self.code.write(": ");
+            self.type_def(type_def);
+        }
+        if let Some(init) = &param.init {
+            self.code.write(" <- ");
+            self.expr(init);
+        }
+    }
+    /// `{` statements `}`, on their own lines.
+    fn body(&mut self, body: &[Statement]) {
+        self.code.write("{").push();
+        for statement in body {
+            self.code.line();
+            self.statement(statement);
+        }
+        self.code.pop().line().write("}");
+    }
+    fn label(&mut self, label: &Option<Identifier>) {
+        if let Some(label) = label {
+            self.identifier(label);
+            self.code.write(": ");
+        }
+    }
+    /// The label of a `break` or `skip`, and the closing `;`.
+    fn jump_label(&mut self, label: &Option<Identifier>) {
+        if let Some(label) = label {
+            self.code.write(" ");
+            self.identifier(label);
+        }
+        self.code.write(";");
+    }
+    fn block(&mut self, block: &Block) {
+        if block.body.is_empty() {
+            match &block.value {
+                Some(value) => {
+                    self.code.write("{ ");
+                    self.expr(value);
+                    self.code.write(" }");
+                }
+                None => {
+                    self.code.write("{}");
+                }
+            }
+            return;
+        }
+        self.code.write("{").push();
+        for statement in block.body.iter() {
+            self.code.line();
+            self.statement(statement);
+        }
+        if let Some(value) = &block.value {
+            self.code.line();
+            self.expr(value);
+        }
+        self.code.pop().line().write("}");
+    }
+    fn destructure(&mut self, destructure: &Destructure) {
+        match destructure {
+            Destructure::Identifier(identifier) => self.identifier(identifier),
+            Destructure::Array(entries) => {
+                self.code.write("[");
+                self.comma(entries, Self::destructure_entry);
+                self.code.write("]");
+            }
+            Destructure::Map(entries) => {
+                self.code.write("{ ");
+                self.comma(entries, Self::destructure_entry);
+                self.code.write(" }");
+            }
+            Destructure::Tuple(entries) => {
+                self.code.write("(");
+                self.comma(entries, Self::destructure_entry);
+                self.code.write(")");
+            }
+        }
+    }
+    fn destructure_entry(&mut self, entry: &DestructureEntry) {
+        match entry {
+            DestructureEntry::Identifier(identifier) => self.identifier(identifier),
+            DestructureEntry::Array(entries) => {
+                self.destructure(&Destructure::Array(entries.clone()))
+            }
+            DestructureEntry::Map(entries) => self.destructure(&Destructure::Map(entries.clone())),
+            DestructureEntry::Tuple(entries) => {
+                self.destructure(&Destructure::Tuple(entries.clone()))
+            }
+            DestructureEntry::Aliasing(key, entry) => {
+                self.identifier(key);
+                self.code.write(": ");
+                self.destructure_entry(entry);
+            }
+            DestructureEntry::Default(entry, value) => {
+                self.destructure_entry(entry);
+                self.code.write(" = ");
+                self.expr(value);
+            }
+            DestructureEntry::Rest(identifier) => {
+                self.code.write("...");
+                self.identifier(identifier);
+            }
+        }
+    }
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard(_) => {
+                self.code.write("_");
+            }
+            Pattern::Literal(expr) => self.expr(expr),
+            Pattern::Binding(identifier) => self.identifier(identifier),
+            Pattern::Array(items, _) => {
+                self.code.write("[");
+                self.comma(items, Self::pattern);
+                self.code.write("]");
+            }
+            Pattern::Tuple(items, _) => {
+                self.code.write("(");
+                self.comma(items, Self::pattern);
+                self.code.write(")");
+            }
+            Pattern::Map(entries, _) => {
+                self.code.write("{ ");
+                self.comma(entries, Self::pattern_entry);
+                self.code.write(" }");
+            }
+            Pattern::Class(class, entries, _) => {
+                self.identifier(class);
+                self.code.write(" { ");
+                self.comma(entries, Self::pattern_entry);
+                self.code.write(" }");
+            }
+            Pattern::Variant(enum_name, variant, items, _) => {
+                self.identifier(enum_name);
+                self.code.write("::");
+                self.identifier(variant);
+                if !items.is_empty() {
+                    self.code.write("(");
+                    self.comma(items, Self::pattern);
+                    self.code.write(")");
+                }
+            }
+        }
+    }
+    fn pattern_entry(&mut self, (key, pattern): &(Identifier, Pattern)) {
+        self.identifier(key);
+        match pattern {
+            Pattern::Binding(binding) if binding.value == key.value => (),
+            pattern => {
+                self.code.write(": ");
+                self.pattern(pattern);
+            }
+        }
+    }
+    fn use_entry(&mut self, entry: &Use) {
+        for (i, segment) in entry.path.iter().enumerate() {
+            if i > 0 {
+                self.code.write("::");
+            }
+            self.identifier(segment);
+        }
+        if let Some(tree) = &entry.use_tree {
+            self.code.write("::{ ");
+            self.comma(tree, Self::use_entry);
+            self.code.write(" }");
+        }
+    }
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Use(entry) => {
+                self.code.write("use ");
+                self.use_entry(entry);
+                self.code.write(";");
+            }
+            Statement::IfStatement(if_statement) => {
+                self.code.write("if ");
+                self.expr(&if_statement.condition);
+                self.code.write(" ");
+                self.body(&if_statement.body);
+                for else_if in if_statement.else_if_blocks.iter() {
+                    self.code.write(" else if ");
+                    self.expr(&else_if.condition);
+                    self.code.write(" ");
+                    self.body(&else_if.body);
+                }
+                if let Some(else_block) = &if_statement.else_block {
+                    self.code.write(" else ");
+                    self.body(else_block);
+                }
+            }
+            Statement::ClassDef(class) => {
+                self.docs(&class.docs);
+                self.annotations(&class.annotations);
+                self.modifiers(&class.modifiers);
+                self.code.write("class ");
+                self.identifier(&class.name);
+                self.type_params(&class.type_params);
+                if let Some(parent) = &class.parent {
+                    self.code.write(": ");
+                    self.identifier(parent);
+                }
+                if !class.traits.is_empty() {
+                    self.code.write(" with ");
+                    self.comma(&class.traits, Self::identifier);
+                }
+                self.code.write(" {").push();
+                for field in class.fields.iter() {
+                    self.code.line();
+                    match field {
+                        ClassField::Fn(method) => self.fn_def(method),
+                        ClassField::Let(field) => self.let_def(field),
+                    }
+                }
+                self.code.pop().line().write("}");
+            }
+            Statement::EnumDef(enum_def) => {
+                self.docs(&enum_def.docs);
+                self.annotations(&enum_def.annotations);
+                self.modifiers(&enum_def.modifiers);
+                self.code.write("enum ");
+                self.identifier(&enum_def.name);
+                self.code.write(" {").push();
+                for variant in enum_def.variants.iter() {
+                    self.code.line();
+                    self.identifier(&variant.name);
+                    if !variant.fields.is_empty() {
+                        self.code.write("(");
+                        self.comma(&variant.fields, Self::identifier);
+                        self.code.write(")");
+                    }
+                    self.code.write(",");
+                }
+                self.code.pop().line().write("}");
+            }
+            Statement::TraitDef(trait_def) => {
+                self.docs(&trait_def.docs);
+                self.annotations(&trait_def.annotations);
+                self.modifiers(&trait_def.modifiers);
+                self.code.write("trait ");
+                self.identifier(&trait_def.name);
+                self.code.write(" {").push();
+                for field in trait_def.fields.iter() {
+                    self.code.line();
+                    match field {
+                        TraitField::Fn(method) => self.fn_def(method),
+                        TraitField::FnSignature(signature) => {
+                            self.docs(&signature.docs);
+                            self.annotations(&signature.annotations);
+                            self.modifiers(&signature.modifiers);
+                            self.code.write("fn ");
+                            self.identifier(&signature.name);
+                            self.code.write("(");
+                            self.comma(&signature.arguments, Self::param);
+                            self.code.write(")");
+                            self.return_type(&signature.return_type);
+                            self.code.write(";");
+                        }
+                    }
+                }
+                self.code.pop().line().write("}");
+            }
+            Statement::MacroDef(macro_def) => {
+                self.docs(&macro_def.docs);
+                self.code.write("macro ");
+                self.identifier(&macro_def.name);
+                self.code.write("!(");
+                self.comma(&macro_def.params, Self::identifier);
+                self.code.write(") ");
+                self.block(&macro_def.body);
+            }
+            Statement::FixityDecl(decl) => {
+                self.docs(&decl.docs);
+                self.annotations(&decl.annotations);
+                self.modifiers(&decl.modifiers);
+                self.code.write(match decl.associativity {
+                    Associativity::Left => "infixl",
+                    Associativity::Right => "infixr",
+                    Associativity::None => "infix",
+                });
+                self.code.write(format!(" {}", decl.precedence));
+                for op in decl.operators.iter() {
+                    self.code.write(format!(" {}", operator(op)));
+                }
+                self.code.write(";");
+            }
+            Statement::Assignment(assignment) => {
+                self.target(&assignment.left);
+                match &assignment.op {
+                    Some(op) => self.code.write(format!(" {}= ", operator(op))),
+                    None => self.code.write(" = "),
+                };
+                self.expr(&assignment.right);
+                self.code.write(";");
+            }
+            Statement::Let(let_def) => self.let_def(let_def),
+            Statement::Fn(fn_def) => self.fn_def(fn_def),
+            Statement::Loop(loop_def) => {
+                self.label(&loop_def.label);
+                self.code.write("loop ");
+                self.body(&loop_def.body);
+            }
+            Statement::While(while_def) => {
+                self.label(&while_def.label);
+                self.code.write("while ");
+                self.expr(&while_def.condition);
+                self.code.write(" ");
+                self.body(&while_def.body);
+            }
+            Statement::For(for_def) => {
+                self.label(&for_def.label);
+                self.code.write("for ");
+                self.destructure(&for_def.assignment);
+                self.code.write(" in ");
+                self.expr(&for_def.expr);
+                self.code.write(" ");
+                self.body(&for_def.body);
+            }
+            Statement::Break(break_stmt) => {
+                self.code.write("break");
+                self.jump_label(&break_stmt.label);
+            }
+            Statement::Skip(skip) => {
+                self.code.write("skip");
+                self.jump_label(&skip.label);
+            }
+            Statement::Try(try_def) => {
+                self.code.write("try ");
+                self.body(&try_def.body);
+                if let Some(catch) = &try_def.catch {
+                    self.code.write(" catch ");
+                    self.identifier(&catch.binding);
+                    self.code.write(" ");
+                    self.body(&catch.body);
+                }
+                if let Some(finally) = &try_def.finally {
+                    self.code.write(" finally ");
+                    self.body(finally);
+                }
+            }
+            Statement::Throw(throw) => {
+                self.code.write("throw ");
+                self.expr(&throw.value);
+                self.code.write(";");
+            }
+            Statement::Defer(defer) => {
+                self.code.write("defer ");
+                self.body(&defer.body);
+            }
+            Statement::Return(ret) => {
+                self.code.write("return");
+                if let Some(value) = &ret.value {
+                    self.code.write(" ");
+                    self.expr(value);
+                }
+                self.code.write(";");
+            }
+            Statement::Expr(expr @ (Expr::IfExpr(_) | Expr::Match(_))) => self.expr(expr),
+            Statement::Expr(expr) => {
+                // Statements can't start with `{`, so lambdas and blocks are
+                // parenthesized.
+                if let Expr::LambdaExpr(_) | Expr::Block(_) = expr {
+                    self.code.write("(");
+                    self.expr(expr);
+                    self.code.write(")");
+                } else {
+                    self.expr(expr);
+                }
+                self.code.write(";");
+            }
+        }
+    }
+    fn let_def(&mut self, let_def: &Let) {
+        self.docs(&let_def.docs);
+        self.annotations(&let_def.annotations);
+        self.modifiers(&let_def.modifiers);
+        self.code.write(if let_def.modifiers.is_const() {
+            "const "
+        } else {
+            "let "
+        });
+        self.destructure(&let_def.name);
+        if let Some(type_def) = &let_def.type_def {
+            self.code.write(": ");
+            self.type_def(type_def);
+        }
+        if let Some(initializer) = &let_def.initializer {
+            self.code.write(" = ");
+            self.expr(initializer);
+        }
+        self.code.write(";");
+    }
+    fn fn_def(&mut self, fn_def: &Fn) {
+        self.docs(&fn_def.docs);
+        self.annotations(&fn_def.annotations);
+        self.modifiers(&fn_def.modifiers);
+        self.code.write("fn ");
+        self.identifier(&fn_def.name);
+        self.type_params(&fn_def.type_params);
+        self.code.write("(");
+        self.comma(&fn_def.arguments, Self::param);
+        self.code.write(")");
+        self.return_type(&fn_def.return_type);
+        self.code.write(" ");
+        self.body(&fn_def.body);
+    }
+    fn target(&mut self, target: &AssignmentTarget) {
+        self.expr(&target.clone().to_expr());
+    }
+    /// Operands that aren't atoms are parenthesized when used as the target
+    /// of a call, member or index expression.
+    fn operand(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(_)
+            | Expr::Call(_)
+            | Expr::Member(_)
+            | Expr::ArrayAccess(_)
+            | Expr::MacroCall(_)
+            | Expr::Number(_)
+            | Expr::Boolean(_)
+            | Expr::SatString(_)
+            | Expr::InterpolatedString(_)
+            | Expr::ArrayLiteral(_)
+            | Expr::ArrayComprehension(_)
+            | Expr::TupleLiteral(_) => self.expr(expr),
+            expr => {
+                self.code.write("(");
+                self.expr(expr);
+                self.code.write(")");
+            }
+        }
+    }
+    fn binary_operand(&mut self, expr: &Expr, min_precedence: u8) {
+        match expr {
+            Expr::Bop(bop) if bop.is_grouped || precedence(&bop.op) < min_precedence => {
+                self.code.write("(");
+                self.expr(expr);
+                self.code.write(")");
+            }
+            expr => self.expr(expr),
+        }
+    }
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Call(call) => {
+                self.operand(&call.target);
+                if call.is_null_safe {
+                    self.code.write("?.");
+                }
+                self.code.write("(");
+                self.comma(&call.arguments, Self::expr);
+                self.code.write(")");
+            }
+            Expr::ArrayAccess(access) => {
+                self.operand(&access.target);
+                if access.is_null_safe {
+                    self.code.write("?.");
+                }
+                self.code.write("[");
+                self.comma(&access.arguments, Self::expr);
+                self.code.write("]");
+            }
+            Expr::Bop(bop) => {
+                let level = precedence(&bop.op);
+                self.binary_operand(&bop.left, level);
+                self.code.write(format!(" {} ", operator(&bop.op)));
+                self.binary_operand(&bop.right, level + 1);
+            }
+            Expr::Uop(uop) => {
+                self.code.write(operator(&uop.op));
+                if let Operator::Not = uop.op {
+                    self.code.write(" ");
+                }
+                self.binary_operand(&uop.expr, u8::MAX);
+            }
+            Expr::LambdaExpr(lambda) => {
+                self.code.write("{ ");
+                if !lambda.params.is_empty() {
+                    self.comma(&lambda.params, Self::param);
+                    if let Some(return_type) = &lambda.return_type {
+                        self.code.write(" -> ");
+                        self.type_def(return_type);
+                    }
+                    self.code.write(" =>");
+                }
+                match &lambda.body[..] {
+                    [
+                        Statement::Return(Return {
+                            value: Some(value), ..
+                        }),
+                    ] => {
+                        self.code.write(" ");
+                        self.expr(value);
+                        self.code.write(" }");
+                    }
+                    body => {
+                        self.code.push();
+                        for statement in body {
+                            self.code.line();
+                            self.statement(statement);
+                        }
+                        self.code.pop().line().write("}");
+                    }
+                }
+            }
+            Expr::Number(Number::Int(value, _)) => {
+                self.code.write(value);
+            }
+            Expr::Number(Number::Float(value, _)) => {
+                self.code.write(format!("{value:?}"));
+            }
+            Expr::Boolean(Boolean::True(_)) => {
+                self.code.write("true");
+            }
+            Expr::Boolean(Boolean::False(_)) => {
+                self.code.write("false");
+            }
+            Expr::SatString(string) => {
+                self.code
+                    .write(format!("\"{}\"", escape_braces(&string.value)));
+            }
+            Expr::InterpolatedString(string) => {
+                self.code.write("\"");
+                for fragment in string.fragments.iter() {
+                    match fragment {
+                        StringFragment::Literal(value) => {
+                            self.code.write(escape_braces(value));
+                        }
+                        StringFragment::Expr(expr) => {
+                            self.code.write("{");
+                            self.expr(expr);
+                            self.code.write("}");
+                        }
+                    }
+                }
+                self.code.write("\"");
+            }
+            Expr::Identifier(identifier) => self.identifier(identifier),
+            Expr::Member(member) => {
+                self.operand(&member.target);
+                self.code.write(match member.op {
+                    MemberOp::Member | MemberOp::Dispatch => ".",
+                    MemberOp::CoalesceMember => "?.",
+                    MemberOp::Static => "::",
+                });
+                self.identifier(&member.field);
+            }
+            Expr::MapLiteral(map) => {
+                self.code.write("'{ ");
+                self.comma(&map.entries, |printer, (key, value)| {
+                    match key {
+                        MapKey::Identifier(key) => printer.identifier(key),
+                        MapKey::SatString(key) => {
+                            printer
+                                .code
+                                .write(format!("\"{}\"", escape_braces(&key.value)));
+                        }
+                        MapKey::Expr(key) => {
+                            printer.code.write("[");
+                            printer.expr(key);
+                            printer.code.write("]");
+                        }
+                    }
+                    printer.code.write(": ");
+                    printer.expr(value);
+                });
+                self.code.write(" }");
+            }
+            Expr::ArrayLiteral(array) => {
+                self.code.write("[");
+                self.comma(&array.values, Self::expr);
+                self.code.write("]");
+            }
+            Expr::TupleLiteral(tuple) => {
+                self.code.write("(");
+                self.comma(&tuple.values, Self::expr);
+                self.code.write(")");
+            }
+            Expr::ArrayComprehension(comprehension) => {
+                self.code.write("[");
+                self.expr(&comprehension.value);
+                self.comprehension_loops(&comprehension.loops);
+                self.code.write("]");
+            }
+            Expr::MapComprehension(comprehension) => {
+                self.code.write("'{ ");
+                match &*comprehension.key {
+                    Expr::Identifier(key) => self.identifier(key),
+                    key => {
+                        self.code.write("[");
+                        self.expr(key);
+                        self.code.write("]");
+                    }
+                }
+                self.code.write(": ");
+                self.expr(&comprehension.value);
+                self.comprehension_loops(&comprehension.loops);
+                self.code.write(" }");
+            }
+            Expr::Match(match_expr) => {
+                self.code.write("match ");
+                self.expr(&match_expr.subject);
+                self.code.write(" {").push();
+                for arm in match_expr.arms.iter() {
+                    self.code.line();
+                    self.pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.code.write(" if ");
+                        self.expr(guard);
+                    }
+                    self.code.write(" => ");
+                    match &*arm.body {
+                        Expr::Block(block) => self.block(block),
+                        Expr::LambdaExpr(_) => self.operand(&arm.body),
+                        body => self.expr(body),
+                    }
+                    self.code.write(",");
+                }
+                self.code.pop().line().write("}");
+            }
+            Expr::IfExpr(if_expr) => self.if_expr(if_expr),
+            Expr::Block(block) => {
+                self.block(block);
+                self.code.write("()");
+            }
+            Expr::Spread(spread) => {
+                self.code.write("...");
+                self.expr(&spread.expr);
+            }
+            Expr::Await(await_expr) => {
+                self.code.write("await ");
+                self.binary_operand(&await_expr.expr, u8::MAX);
+            }
+            Expr::MacroCall(call) => {
+                self.identifier(&call.name);
+                self.code.write("!(");
+                self.comma(&call.arguments, Self::expr);
+                self.code.write(")");
+            }
+        }
+    }
+    fn comprehension_loops(&mut self, loops: &[ComprehensionLoop]) {
+        for comprehension_loop in loops {
+            self.code.write(" for ");
+            self.destructure(&comprehension_loop.assignment);
+            self.code.write(" in ");
+            self.expr(&comprehension_loop.expr);
+            if let Some(condition) = &comprehension_loop.condition {
+                self.code.write(" if ");
+                self.expr(condition);
+            }
+        }
+    }
+    fn if_expr(&mut self, if_expr: &IfExpr) {
+        self.code.write("if ");
+        self.expr(&if_expr.condition);
+        self.code.write(" ");
+        self.block(&if_expr.body);
+        match if_expr.else_block.as_deref() {
+            Some(Expr::IfExpr(else_if)) => {
+                self.code.write(" else ");
+                self.if_expr(else_if);
+            }
+            Some(Expr::Block(block)) => {
+                self.code.write(" else ");
+                self.block(block);
+            }
+            Some(other) => {
+                self.code.write(" else { ");
+                self.expr(other);
+                self.code.write(" }");
+            }
+            None => (),
+        }
+    }
+}
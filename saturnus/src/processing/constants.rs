@@ -0,0 +1,180 @@
+//! Constant bindings, checked and inlined before code generation.
+//!
+//! `const` bindings can't be assigned to, and the ones initialized with a
+//! literal are replaced by their value wherever they are read. Constant class
+//! fields can't be assigned to either, be it through `self` in the methods of
+//! the class or through the class itself.
+
+use std::collections::HashMap;
+
+use crate::{
+    diagnostics::Diagnostic,
+    parsing::{
+        ast::{AssignmentTarget, ClassField, Destructure, Expr, Fn, Identifier, Statement},
+        builders::LeafCollector,
+    },
+    processing::visitor::{
+        VisitorMut, walk_expr, walk_fn, walk_statement, walk_statements, walk_target,
+    },
+};
+
+/// Checks and inlines the constants of the program, see the module docs.
+pub fn inline_constants(program: &mut Vec<Statement>) -> Result<(), Diagnostic> {
+    Constants::default().visit_statements(program)
+}
+
+enum Binding {
+    Variable,
+    /// Holds the value when known at compile time.
+    Constant(Option<Expr>),
+}
+
+#[derive(Default)]
+struct Constants {
+    /// Names declared in each scope, innermost last.
+    scopes: Vec<HashMap<String, Binding>>,
+    /// Constant fields of the classes declared so far.
+    classes: HashMap<String, Vec<String>>,
+    /// Class whose methods are being visited, for `self`.
+    class: Option<String>,
+}
+impl Constants {
+    fn resolve(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+    fn declare(&mut self, name: &Identifier, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.value.clone(), binding);
+        }
+    }
+    fn scoped<T>(&mut self, visit: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = visit(self);
+        self.scopes.pop();
+        result
+    }
+    fn is_const_field(&self, owner: &Identifier, field: &Identifier) -> bool {
+        let class = if owner.value == "self" {
+            self.class.as_deref()
+        } else {
+            Some(owner.value.as_str())
+        };
+        class
+            .and_then(|class| self.classes.get(class))
+            .is_some_and(|fields| fields.contains(&field.value))
+    }
+    fn is_known(value: &Expr) -> bool {
+        matches!(
+            value,
+            Expr::Number(_) | Expr::Boolean(_) | Expr::SatString(_)
+        )
+    }
+}
+impl VisitorMut for Constants {
+    type Error = Diagnostic;
+
+    fn visit_statements(&mut self, statements: &mut Vec<Statement>) -> Result<(), Diagnostic> {
+        self.scoped(|this| walk_statements(this, statements))
+    }
+    fn visit_statement(&mut self, statement: &mut Statement) -> Result<(), Diagnostic> {
+        match statement {
+            Statement::Let(let_def) if let_def.modifiers.is_const() => {
+                let Some(initializer) = &let_def.initializer else {
+                    return Err(Diagnostic::new(
+                        "constants must be initialized",
+                        let_def.span,
+                    ));
+                };
+                let value = match &let_def.name {
+                    Destructure::Identifier(_) if Self::is_known(initializer) => {
+                        Some(initializer.clone())
+                    }
+                    _ => None,
+                };
+                walk_statement(self, statement)?;
+                let Statement::Let(let_def) = statement else {
+                    unreachable!();
+                };
+                for name in let_def.name.collect_leaves() {
+                    self.declare(&name, Binding::Constant(value.clone()));
+                }
+                Ok(())
+            }
+            Statement::ClassDef(class) => {
+                let fields = class
+                    .fields
+                    .iter()
+                    .filter_map(|field| match field {
+                        ClassField::Let(field) if field.modifiers.is_const() => Some(field),
+                        _ => None,
+                    })
+                    .flat_map(|field| field.name.collect_leaves())
+                    .map(|name| name.value)
+                    .collect();
+                self.classes.insert(class.name.value.clone(), fields);
+                let outer = self.class.replace(class.name.value.clone());
+                let result = walk_statement(self, statement);
+                self.class = outer;
+                result
+            }
+            // Loop variables and caught errors are only in scope of the body.
+            Statement::For(_) | Statement::Try(_) => {
+                self.scoped(|this| walk_statement(this, statement))
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), Diagnostic> {
+        match expr {
+            Expr::Identifier(identifier) => {
+                if let Some(Binding::Constant(Some(value))) = self.resolve(&identifier.value) {
+                    *expr = value.clone().with_span(identifier.span);
+                }
+                Ok(())
+            }
+            Expr::LambdaExpr(_)
+            | Expr::Match(_)
+            | Expr::ArrayComprehension(_)
+            | Expr::MapComprehension(_) => self.scoped(|this| walk_expr(this, expr)),
+            _ => walk_expr(self, expr),
+        }
+    }
+    fn visit_fn(&mut self, fn_def: &mut Fn) -> Result<(), Diagnostic> {
+        self.scoped(|this| walk_fn(this, fn_def))
+    }
+    fn visit_target(&mut self, target: &mut AssignmentTarget) -> Result<(), Diagnostic> {
+        match target {
+            AssignmentTarget::Identifier(identifier) => {
+                if let Some(Binding::Constant(_)) = self.resolve(&identifier.value) {
+                    return Err(Diagnostic::new(
+                        format!(
+                            "`{}` is a constant and can't be assigned to",
+                            identifier.value
+                        ),
+                        identifier.span,
+                    ));
+                }
+                Ok(())
+            }
+            AssignmentTarget::Member(member) => {
+                if let Expr::Identifier(owner) = &*member.target {
+                    if self.is_const_field(owner, &member.field) {
+                        return Err(Diagnostic::new(
+                            format!(
+                                "`{}` is a constant field and can't be assigned to",
+                                member.field.value
+                            ),
+                            member.span,
+                        ));
+                    }
+                }
+                self.visit_expr(&mut member.target)
+            }
+            AssignmentTarget::ArrayAccess(_) => walk_target(self, target),
+        }
+    }
+    fn visit_binding(&mut self, binding: &mut Identifier) -> Result<(), Diagnostic> {
+        self.declare(binding, Binding::Variable);
+        Ok(())
+    }
+}
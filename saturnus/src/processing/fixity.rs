@@ -0,0 +1,291 @@
+//! Operator precedence and associativity, applied after macro expansion.
+//!
+//! The grammar gives custom operators the loosest binding power, left
+//! associative. `infixl`, `infixr` and `infix` declarations change that for
+//! the whole unit (and for the units importing them, when public): every
+//! chain of binary operators that was not parenthesized is flattened and
+//! rebuilt following the declared fixities. Built-in operators keep the
+//! precedence of the grammar tiers, except for `..` which is declared by the
+//! standard library along with `|>`.
+
+use std::{collections::HashMap, fmt::Display};
+
+use lazy_static::lazy_static;
+
+use crate::{
+    diagnostics::Diagnostic,
+    parsing::{
+        ast::{
+            Associativity, Bop, Expr, FixityDecl, IntoExpr, Operator, Span, Statement,
+            TupleLiteral, Use,
+        },
+        parse_program,
+        printer::operator,
+    },
+    processing::visitor::{VisitorMut, walk_expr, walk_statement},
+};
+
+lazy_static! {
+    /// Declared by the standard library, in scope for every unit.
+    static ref STD_FIXITIES: Vec<FixityDecl> = {
+        let (program, _) = parse_program(ststd::STDLIB_CODE);
+        exported(&program)
+    };
+}
+
+/// The public fixity declarations of a program, to be passed to the units
+/// importing it through `CompilerOptions::fixities`.
+pub fn exported(program: &[Statement]) -> Vec<FixityDecl> {
+    program
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::FixityDecl(decl) if decl.modifiers.is_pub() => Some(decl.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The public fixity declarations of the units a program uses, for drivers
+/// to pass through `CompilerOptions::fixities`. `load` gives the source of the
+/// unit at a module path, if any: a path naming a member of a unit falls back
+/// to the unit itself.
+pub fn imported(
+    program: &[Statement],
+    mut load: impl FnMut(&[String]) -> Option<String>,
+) -> Vec<FixityDecl> {
+    fn leaves(use_stmt: &Use, mut root: Vec<String>, paths: &mut Vec<Vec<String>>) {
+        root.extend(use_stmt.path.iter().map(|segment| segment.value.clone()));
+        match &use_stmt.use_tree {
+            Some(tree) => {
+                for item in tree {
+                    leaves(item, root.clone(), paths);
+                }
+            }
+            None => paths.push(root),
+        }
+    }
+    let mut paths = vec![];
+    for statement in program {
+        if let Statement::Use(use_stmt) = statement {
+            leaves(use_stmt, vec![], &mut paths);
+        }
+    }
+    paths
+        .iter()
+        .filter_map(|path| (1..=path.len()).rev().find_map(|len| load(&path[..len])))
+        .flat_map(|source| exported(&parse_program(&source).0))
+        .collect()
+}
+
+/// Re-associates the operator chains of the program, see the module docs.
+/// The declarations are taken out of the program.
+pub fn reassociate(
+    program: Vec<Statement>,
+    imported: &[FixityDecl],
+) -> Result<Vec<Statement>, Diagnostic> {
+    let mut fixities = Fixities::default();
+    for decl in STD_FIXITIES.iter() {
+        fixities.declare(decl, "by the standard library")?;
+    }
+    for decl in imported {
+        fixities.declare(decl, "by an imported unit")?;
+    }
+    let mut statements = vec![];
+    for statement in program {
+        match statement {
+            Statement::FixityDecl(decl) => fixities.declare(&decl, "earlier in this unit")?,
+            statement => statements.push(statement),
+        }
+    }
+    fixities.visit_statements(&mut statements)?;
+    Ok(statements)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fixity {
+    associativity: Associativity,
+    /// Higher binds tighter.
+    precedence: u8,
+}
+impl Fixity {
+    /// Levels of the grammar tiers, spaced so declarations can fit between.
+    fn builtin(op: &Operator) -> Self {
+        let precedence = match op {
+            Operator::Custom(_) => 0,
+            Operator::And | Operator::Or => 2,
+            Operator::Lt
+            | Operator::LtEq
+            | Operator::Gt
+            | Operator::GtEq
+            | Operator::Eq
+            | Operator::Neq => 3,
+            Operator::Coalesce => 4,
+            Operator::Add | Operator::Sub | Operator::StrCat => 6,
+            Operator::Mul | Operator::Div => 7,
+            Operator::Pow | Operator::Range => 8,
+            _ => 9,
+        };
+        Fixity {
+            associativity: Associativity::Left,
+            precedence,
+        }
+    }
+}
+impl Display for Fixity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keyword = match self.associativity {
+            Associativity::Left => "infixl",
+            Associativity::Right => "infixr",
+            Associativity::None => "infix",
+        };
+        write!(f, "{keyword} {}", self.precedence)
+    }
+}
+
+#[derive(Default)]
+struct Fixities {
+    /// Along with where the declaration comes from, to report conflicts.
+    declared: HashMap<Operator, (Fixity, &'static str)>,
+}
+impl Fixities {
+    /// Declaring the same fixity twice is allowed, as units may import the
+    /// same declarations from several places.
+    fn declare(&mut self, decl: &FixityDecl, origin: &'static str) -> Result<(), Diagnostic> {
+        let fixity = Fixity {
+            associativity: decl.associativity,
+            precedence: decl.precedence,
+        };
+        for op in decl.operators.iter() {
+            match self.declared.insert(op.clone(), (fixity, origin)) {
+                Some((previous, previous_origin)) if previous != fixity => {
+                    return Err(Diagnostic::new(
+                        format!(
+                            "the operator `{op}` is declared as `{fixity} {op}` here, and as `{previous} {op}` {previous_origin}",
+                            op = operator(op)
+                        ),
+                        decl.span,
+                    ));
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+    fn fixity(&self, op: &Operator) -> Fixity {
+        self.declared
+            .get(op)
+            .map(|(fixity, _)| *fixity)
+            .unwrap_or_else(|| Fixity::builtin(op))
+    }
+    /// Whether the operator on the left takes the operand in between.
+    fn binds_left(
+        &self,
+        left: &Operator,
+        right: &Operator,
+        span: Span,
+    ) -> Result<bool, Diagnostic> {
+        let (a, b) = (self.fixity(left), self.fixity(right));
+        if a.precedence != b.precedence {
+            return Ok(a.precedence > b.precedence);
+        }
+        match (a.associativity, b.associativity) {
+            (Associativity::Left, Associativity::Left) => Ok(true),
+            (Associativity::Right, Associativity::Right) => Ok(false),
+            (Associativity::None, Associativity::None) if left == right => Err(Diagnostic::new(
+                format!(
+                    "`{}` is not associative, use parentheses to group the operands",
+                    operator(left)
+                ),
+                span,
+            )),
+            _ => Err(Diagnostic::new(
+                format!(
+                    "cannot mix `{}` (`{a}`) and `{}` (`{b}`) in the same expression, use parentheses to group the operands",
+                    operator(left),
+                    operator(right)
+                ),
+                span,
+            )),
+        }
+    }
+    /// Shunting-yard over the flattened chain.
+    fn rebuild(
+        &self,
+        operands: Vec<Expr>,
+        operators: Vec<Operator>,
+        span: Span,
+    ) -> Result<Expr, Diagnostic> {
+        fn reduce(output: &mut Vec<Expr>, op: Operator) {
+            let right = output.pop().unwrap();
+            let left = output.pop().unwrap();
+            output.push(Bop::new(left, op, right));
+        }
+        let mut operands = operands.into_iter();
+        let mut output = vec![operands.next().unwrap()];
+        let mut stack: Vec<Operator> = vec![];
+        for (op, operand) in operators.into_iter().zip(operands) {
+            while let Some(top) = stack.last() {
+                if !self.binds_left(top, &op, span)? {
+                    break;
+                }
+                let top = stack.pop().unwrap();
+                reduce(&mut output, top);
+            }
+            stack.push(op);
+            output.push(operand);
+        }
+        while let Some(top) = stack.pop() {
+            reduce(&mut output, top);
+        }
+        Ok(output.pop().unwrap())
+    }
+}
+impl VisitorMut for Fixities {
+    type Error = Diagnostic;
+
+    fn visit_statement(&mut self, statement: &mut Statement) -> Result<(), Diagnostic> {
+        if let Statement::FixityDecl(decl) = statement {
+            return Err(Diagnostic::new(
+                "operator fixities can only be declared at the top level",
+                decl.span,
+            ));
+        }
+        walk_statement(self, statement)
+    }
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), Diagnostic> {
+        if !matches!(expr, Expr::Bop(_)) {
+            return walk_expr(self, expr);
+        }
+        let Expr::Bop(bop) = std::mem::replace(expr, TupleLiteral::unit().into_expr()) else {
+            unreachable!();
+        };
+        let (span, is_grouped) = (bop.span, bop.is_grouped);
+        let mut operands = vec![];
+        let mut operators = vec![];
+        flatten(*bop.left, &mut operands, &mut operators);
+        operators.push(bop.op);
+        flatten(*bop.right, &mut operands, &mut operators);
+        for operand in operands.iter_mut() {
+            self.visit_expr(operand)?;
+        }
+        let rebuilt = self.rebuild(operands, operators, span)?;
+        *expr = if is_grouped {
+            rebuilt.grouped()
+        } else {
+            rebuilt
+        };
+        Ok(())
+    }
+}
+
+/// Collects the operands and operators of a chain, stopping at parentheses.
+fn flatten(expr: Expr, operands: &mut Vec<Expr>, operators: &mut Vec<Operator>) {
+    match expr {
+        Expr::Bop(bop) if !bop.is_grouped => {
+            flatten(*bop.left, operands, operators);
+            operators.push(bop.op);
+            flatten(*bop.right, operands, operators);
+        }
+        expr => operands.push(expr),
+    }
+}
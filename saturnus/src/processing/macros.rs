@@ -0,0 +1,336 @@
+//! Macro expansion, run between parsing and code generation.
+//!
+//! `macro name!(a, b) { ... }` definitions are taken out of the program and
+//! every `name!(x, y)` call is replaced by the body of the macro, with the
+//! parameters substituted by the argument fragments. Expansion is hygienic:
+//! names declared by the body are renamed apart, so they never capture nor
+//! shadow the names of the call site. A parameter used where a name is
+//! declared is replaced by the argument instead, which lets macros declare
+//! variables on behalf of the caller.
+//!
+//! Calls in statement position are spliced into the enclosing block, while
+//! calls in expression position become block expressions (or the bare value,
+//! for bodies without statements).
+
+use std::{collections::HashMap, convert::Infallible};
+
+use crate::{
+    compiler::CompilerError,
+    diagnostics::Diagnostic,
+    parsing::{
+        ast::{
+            AssignmentTarget, Block, Call, Expr, Identifier, IntoExpr, MacroCall, MacroDef,
+            SatString, Span, Spanned, Statement,
+        },
+        parse_program,
+        printer::print_program,
+    },
+    processing::visitor::{VisitorMut, walk_block, walk_expr, walk_target},
+    source::SourceCode,
+};
+
+/// Maximum depth of nested expansions, stops macros that expand to themselves.
+pub const RECURSION_LIMIT: usize = 64;
+
+/// Expands every macro call of the program, see the module docs.
+pub fn expand_macros(program: Vec<Statement>) -> Result<Vec<Statement>, Diagnostic> {
+    MacroExpander::default().expand_program(program)
+}
+
+/// Parses the source and expands its macros, giving back the expanded program
+/// as Saturnus code.
+pub fn expand_source(source: impl SourceCode) -> Result<String, CompilerError> {
+    let file = source.file();
+    let code = source.source();
+    let (ast, errors) = parse_program(&code);
+    if !errors.is_empty() {
+        return Err(CompilerError::SyntaxErrors(errors).locate(&code, file));
+    }
+    let ast = expand_macros(ast)
+        .map_err(|err| CompilerError::MacroError(Box::new(err)).locate(&code, file))?;
+    Ok(print_program(&ast))
+}
+
+#[derive(Default)]
+pub struct MacroExpander {
+    macros: HashMap<String, MacroDef>,
+    depth: usize,
+    /// Suffix of the next hygienic name.
+    fresh: usize,
+}
+impl MacroExpander {
+    /// Macros are collected from the top level of the program before any
+    /// expansion, so they can be used ahead of their definition.
+    pub fn expand_program(mut self, program: Vec<Statement>) -> Result<Vec<Statement>, Diagnostic> {
+        let mut statements = vec![];
+        for statement in program {
+            match statement {
+                Statement::MacroDef(def) => self.define(def)?,
+                statement => statements.push(statement),
+            }
+        }
+        self.visit_statements(&mut statements)?;
+        Ok(statements)
+    }
+    fn define(&mut self, def: MacroDef) -> Result<(), Diagnostic> {
+        if def.name.value == "native" || self.macros.contains_key(&def.name.value) {
+            return Err(Diagnostic::new(
+                format!("the macro `{}!` is defined multiple times", def.name.value),
+                def.name.span,
+            ));
+        }
+        for (i, param) in def.params.iter().enumerate() {
+            if def.params[..i].iter().any(|p| p.value == param.value) {
+                return Err(Diagnostic::new(
+                    format!(
+                        "the parameter `{}` is declared twice by `{}!`",
+                        param.value, def.name.value
+                    ),
+                    param.span,
+                ));
+            }
+        }
+        self.macros.insert(def.name.value.clone(), def);
+        Ok(())
+    }
+    fn expand(&mut self, call: MacroCall) -> Result<Block, Diagnostic> {
+        let MacroCall {
+            name,
+            mut arguments,
+            span,
+        } = call;
+        for argument in arguments.iter_mut() {
+            self.visit_expr(argument)?;
+        }
+        if name.value == "native" {
+            return expand_native(arguments, span);
+        }
+        let Some(def) = self.macros.get(&name.value) else {
+            return Err(Diagnostic::new(
+                format!("cannot find macro `{}!` in this scope", name.value),
+                name.span,
+            ));
+        };
+        if def.params.len() != arguments.len() {
+            return Err(Diagnostic::new(
+                format!(
+                    "the macro `{}!` takes {} argument(s) but {} were given",
+                    name.value,
+                    def.params.len(),
+                    arguments.len()
+                ),
+                span,
+            ));
+        }
+        if self.depth >= RECURSION_LIMIT {
+            return Err(Diagnostic::new(
+                format!("recursion limit reached while expanding `{}!`", name.value),
+                span,
+            ));
+        }
+        let mut body = def.body.clone();
+        let params = def.params.clone();
+        let mut hygiene = Hygiene {
+            params: &params,
+            renames: HashMap::new(),
+            fresh: &mut self.fresh,
+            collecting: true,
+        };
+        let Ok(()) = walk_block(&mut hygiene, &mut body);
+        hygiene.collecting = false;
+        let Ok(()) = walk_block(&mut hygiene, &mut body);
+        let mut substitution = Substitution {
+            macro_name: &name,
+            arguments: params
+                .iter()
+                .map(|param| param.value.clone())
+                .zip(arguments)
+                .collect(),
+        };
+        walk_block(&mut substitution, &mut body)?;
+        // The expansion may call other macros (or this one) in turn.
+        self.depth += 1;
+        let result = walk_block(self, &mut body);
+        self.depth -= 1;
+        result?;
+        body.span = span;
+        Ok(body)
+    }
+}
+impl VisitorMut for MacroExpander {
+    type Error = Diagnostic;
+
+    fn visit_statements(&mut self, statements: &mut Vec<Statement>) -> Result<(), Diagnostic> {
+        let mut expanded = Vec::with_capacity(statements.len());
+        for mut statement in std::mem::take(statements) {
+            match statement {
+                Statement::Expr(Expr::MacroCall(call)) => {
+                    let Block { body, value, .. } = self.expand(call)?;
+                    expanded.extend(body);
+                    expanded.extend(value.map(|value| Statement::Expr(*value)));
+                }
+                Statement::MacroDef(def) => {
+                    return Err(Diagnostic::new(
+                        "macros can only be defined at the top level",
+                        def.span,
+                    ));
+                }
+                _ => {
+                    self.visit_statement(&mut statement)?;
+                    expanded.push(statement);
+                }
+            }
+        }
+        *statements = expanded;
+        Ok(())
+    }
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), Diagnostic> {
+        let Expr::MacroCall(call) = expr else {
+            return walk_expr(self, expr);
+        };
+        let block = self.expand(call.clone())?;
+        *expr = match block {
+            Block {
+                body,
+                value: Some(value),
+                ..
+            } if body.is_empty() => value.grouped(),
+            block => Expr::Block(block),
+        };
+        Ok(())
+    }
+}
+
+/// `native!()`, stands for the body of functions provided by the host
+/// runtime, which replaces them once the module is loaded. Calling one that
+/// was not replaced raises an error.
+fn expand_native(arguments: Vec<Expr>, span: Span) -> Result<Block, Diagnostic> {
+    if let Some(argument) = arguments.first() {
+        return Err(Diagnostic::new(
+            "the macro `native!` takes no arguments",
+            argument.span(),
+        ));
+    }
+    let message = SatString {
+        value: "not implemented by the native runtime".into(),
+        span,
+    };
+    let raise = Call::new(
+        Identifier::new("error", false),
+        vec![message.into_expr()],
+        false,
+    );
+    Ok(Block::new(vec![], Some(raise.with_span(span)), span))
+}
+
+/// Renames the names declared by a macro body, first collecting them and then
+/// replacing every declaration and use.
+struct Hygiene<'a> {
+    params: &'a [Identifier],
+    renames: HashMap<String, String>,
+    fresh: &'a mut usize,
+    collecting: bool,
+}
+impl Hygiene<'_> {
+    fn rename(&self, identifier: &mut Identifier) {
+        if let Some(renamed) = self.renames.get(&identifier.value) {
+            identifier.value = renamed.clone();
+        }
+    }
+}
+impl VisitorMut for Hygiene<'_> {
+    type Error = Infallible;
+
+    fn visit_binding(&mut self, binding: &mut Identifier) -> Result<(), Infallible> {
+        if !self.collecting {
+            self.rename(binding);
+        } else if !binding.is_escaped
+            && !binding.is_void()
+            && !self.renames.contains_key(&binding.value)
+            && !self.params.iter().any(|param| param.value == binding.value)
+        {
+            *self.fresh += 1;
+            let renamed = format!("__{}_{}__", binding.value, self.fresh);
+            self.renames.insert(binding.value.clone(), renamed);
+        }
+        Ok(())
+    }
+    fn visit_reference(&mut self, reference: &mut Identifier) -> Result<(), Infallible> {
+        if !self.collecting {
+            self.rename(reference);
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the parameters of a macro body by the argument fragments.
+struct Substitution<'a> {
+    macro_name: &'a Identifier,
+    arguments: HashMap<String, Expr>,
+}
+impl Substitution<'_> {
+    fn argument(&self, identifier: &Identifier) -> Option<&Expr> {
+        if identifier.is_escaped {
+            return None;
+        }
+        self.arguments.get(&identifier.value)
+    }
+    /// Parameters in place of a name can only take a name.
+    fn name(&self, identifier: &mut Identifier) -> Result<(), Diagnostic> {
+        match self.argument(identifier) {
+            Some(Expr::Identifier(name)) => *identifier = name.clone(),
+            Some(argument) => {
+                return Err(Diagnostic::new(
+                    format!(
+                        "`{}` is used as a name by `{}!`, so the argument must be a name",
+                        identifier.value, self.macro_name.value
+                    ),
+                    argument.span(),
+                ));
+            }
+            None => (),
+        }
+        Ok(())
+    }
+}
+impl VisitorMut for Substitution<'_> {
+    type Error = Diagnostic;
+
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), Diagnostic> {
+        if let Expr::Identifier(identifier) = expr {
+            if let Some(argument) = self.argument(identifier) {
+                // Kept as a whole, as if it was parenthesized.
+                *expr = argument.clone().grouped();
+                return Ok(());
+            }
+        }
+        walk_expr(self, expr)
+    }
+    fn visit_target(&mut self, target: &mut AssignmentTarget) -> Result<(), Diagnostic> {
+        let AssignmentTarget::Identifier(identifier) = target else {
+            return walk_target(self, target);
+        };
+        *target = match self.argument(identifier) {
+            Some(Expr::Identifier(value)) => AssignmentTarget::Identifier(value.clone()),
+            Some(Expr::Member(value)) => AssignmentTarget::Member(value.clone()),
+            Some(Expr::ArrayAccess(value)) => AssignmentTarget::ArrayAccess(value.clone()),
+            Some(argument) => {
+                return Err(Diagnostic::new(
+                    format!(
+                        "`{}` is assigned by `{}!`, so the argument must be a variable, a member or an index",
+                        identifier.value, self.macro_name.value
+                    ),
+                    argument.span(),
+                ));
+            }
+            None => return Ok(()),
+        };
+        Ok(())
+    }
+    fn visit_binding(&mut self, binding: &mut Identifier) -> Result<(), Diagnostic> {
+        self.name(binding)
+    }
+    fn visit_reference(&mut self, reference: &mut Identifier) -> Result<(), Diagnostic> {
+        self.name(reference)
+    }
+}
@@ -1,2 +1,6 @@
+pub mod constants;
+pub mod fixity;
 pub mod macros;
 pub mod modules;
+pub mod optional;
+pub mod visitor;
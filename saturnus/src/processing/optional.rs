@@ -0,0 +1,218 @@
+//! Lowering of the null-safe operators, run before code generation.
+//!
+//! `a ?? b` and the optional chains (`a?.b`, `a?.[i]` and `a?.(x)`) become
+//! block expressions that test a temporary, so the target is evaluated once
+//! and `false` is kept as a value instead of being taken for unit. The rest of
+//! an optional chain is moved inside the test, so `a?.b.c` is unit when `a`
+//! is, rather than failing on `.c`.
+//!
+//! As blocks, they compile to plain statements when they are the value of a
+//! `let`, an assignment or a `return`, and to a function call anywhere else.
+
+use std::convert::Infallible;
+
+use crate::{
+    parsing::ast::{
+        Block, Bop, DefModifiers, Expr, Identifier, IfExpr, IntoExpr, IntoStatement, Let, Member,
+        MemberOp, Operator, Span, Spanned, Statement, TupleLiteral,
+    },
+    processing::visitor::{VisitorMut, walk_expr},
+};
+
+/// Lowers every `??` and optional chain of the program, see the module docs.
+pub fn lower_optionals(program: &mut Vec<Statement>) {
+    let Ok(()) = Optionals::default().visit_statements(program);
+}
+
+#[derive(Default)]
+struct Optionals {
+    /// Suffix of the next temporary.
+    fresh: usize,
+}
+impl Optionals {
+    /// Binds the value to a temporary, unless it is a name or a literal which
+    /// can be read again.
+    fn bind(&mut self, value: Expr) -> (Option<Statement>, Expr) {
+        if let Expr::Identifier(_) | Expr::Number(_) | Expr::Boolean(_) | Expr::SatString(_) = value
+        {
+            return (None, value);
+        }
+        self.fresh_binding(value)
+    }
+    /// Binds the target of a chain to a temporary unless it is a name, as
+    /// Lua can't index literals (`nil.x` is not valid).
+    fn bind_receiver(&mut self, value: Expr) -> (Option<Statement>, Expr) {
+        match &value {
+            Expr::Identifier(name) if name.value != "nil" => (None, value),
+            _ => self.fresh_binding(value),
+        }
+    }
+    fn fresh_binding(&mut self, value: Expr) -> (Option<Statement>, Expr) {
+        self.fresh += 1;
+        let name = Identifier::new(format!("__optional_{}__", self.fresh), false);
+        let binding = Let::new(name.clone().unwrap_identifier(), DefModifiers::new(), value);
+        (Some(binding.into_statement()), name)
+    }
+    /// `{ let t = ...; if tested != () { present } else { absent } }`
+    fn test(
+        binding: Option<Statement>,
+        tested: Expr,
+        absent: Expr,
+        present: Expr,
+        span: Span,
+    ) -> Expr {
+        let condition = Bop::new(tested, Operator::Neq, TupleLiteral::unit().into_expr());
+        let test = Expr::IfExpr(IfExpr {
+            condition: Box::new(condition),
+            body: Block::new(vec![], Some(present), span),
+            else_block: Some(Box::new(absent)),
+            span,
+        });
+        match binding {
+            Some(binding) => Expr::Block(Block::new(vec![binding], Some(test), span)),
+            None => test,
+        }
+    }
+    fn coalesce(&mut self, value: Expr, default: Expr, span: Span) -> Expr {
+        let (binding, value) = self.bind(value);
+        Self::test(binding, value.clone(), default, value, span)
+    }
+    /// Applies the links in order, from the first null-safe one on they are
+    /// only applied when the target is not unit.
+    fn chain(&mut self, target: Expr, mut links: Vec<Expr>) -> Expr {
+        let Some(position) = links.iter().position(is_null_safe) else {
+            return links.into_iter().fold(target, attach);
+        };
+        let mut rest = links.split_off(position).into_iter();
+        let target = links.into_iter().fold(target, attach);
+        let span = rest.as_slice().last().map_or(target.span(), Spanned::span);
+        let first = rest.next().expect("Split at a link");
+        let rest = rest.collect::<Vec<_>>();
+        match (target, &first) {
+            // Methods are tested on the object, so they are still called
+            // with it as `self`.
+            (Expr::Member(member), Expr::Call(_)) if matches!(member.op, MemberOp::Member) => {
+                let Member {
+                    target: object,
+                    op,
+                    field,
+                    span: member_span,
+                } = member;
+                let (binding, object) = self.bind_receiver(*object);
+                let method = Expr::Member(Member {
+                    target: Box::new(object),
+                    op,
+                    field,
+                    span: member_span,
+                });
+                let present = self.chain(attach(method.clone(), first), rest);
+                Self::test(
+                    binding,
+                    method,
+                    TupleLiteral::unit().into_expr(),
+                    present,
+                    span,
+                )
+            }
+            (target, _) => {
+                let (binding, target) = self.bind_receiver(target);
+                let present = self.chain(attach(target.clone(), first), rest);
+                Self::test(
+                    binding,
+                    target,
+                    TupleLiteral::unit().into_expr(),
+                    present,
+                    span,
+                )
+            }
+        }
+    }
+}
+impl VisitorMut for Optionals {
+    type Error = Infallible;
+
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), Infallible> {
+        match expr {
+            Expr::Bop(bop) if bop.op == Operator::Coalesce => {
+                walk_expr(self, expr)?;
+                let Expr::Bop(bop) = std::mem::replace(expr, TupleLiteral::unit().into_expr())
+                else {
+                    unreachable!();
+                };
+                *expr = self.coalesce(*bop.left, *bop.right, bop.span);
+            }
+            Expr::Member(_) | Expr::ArrayAccess(_) | Expr::Call(_) if has_null_safe_link(expr) => {
+                let mut links = vec![];
+                let mut target = std::mem::replace(expr, TupleLiteral::unit().into_expr());
+                while let Some(inner) = detach(&mut target) {
+                    links.push(std::mem::replace(&mut target, inner));
+                }
+                links.reverse();
+                self.visit_expr(&mut target)?;
+                for link in links.iter_mut() {
+                    // Only the arguments are left to visit.
+                    walk_expr(self, link)?;
+                }
+                *expr = self.chain(target, links);
+            }
+            _ => walk_expr(self, expr)?,
+        }
+        Ok(())
+    }
+}
+
+fn is_null_safe(link: &Expr) -> bool {
+    match link {
+        Expr::Member(member) => matches!(member.op, MemberOp::CoalesceMember),
+        Expr::ArrayAccess(access) => access.is_null_safe,
+        Expr::Call(call) => call.is_null_safe,
+        _ => false,
+    }
+}
+
+fn has_null_safe_link(expr: &Expr) -> bool {
+    let target = match expr {
+        Expr::Member(member) => &member.target,
+        Expr::ArrayAccess(access) => &access.target,
+        Expr::Call(call) => &call.target,
+        _ => return false,
+    };
+    is_null_safe(expr) || has_null_safe_link(target)
+}
+
+/// Takes the target out of a link of a chain, leaving unit in its place.
+fn detach(link: &mut Expr) -> Option<Expr> {
+    let target = match link {
+        Expr::Member(member) => &mut member.target,
+        Expr::ArrayAccess(access) => &mut access.target,
+        Expr::Call(call) => &mut call.target,
+        _ => return None,
+    };
+    Some(std::mem::replace(
+        &mut **target,
+        TupleLiteral::unit().into_expr(),
+    ))
+}
+
+/// Puts the target back into a link, which is no longer null-safe as the
+/// target was tested already.
+fn attach(target: Expr, mut link: Expr) -> Expr {
+    match &mut link {
+        Expr::Member(member) => {
+            if let MemberOp::CoalesceMember = member.op {
+                member.op = MemberOp::Member;
+            }
+            member.target = Box::new(target);
+        }
+        Expr::ArrayAccess(access) => {
+            access.is_null_safe = false;
+            access.target = Box::new(target);
+        }
+        Expr::Call(call) => {
+            call.is_null_safe = false;
+            call.target = Box::new(target);
+        }
+        _ => unreachable!("Only chain links are detached"),
+    }
+    link
+}
@@ -0,0 +1,430 @@
+use crate::parsing::ast::{
+    Annotations, AssignmentTarget, Block, ClassField, ComprehensionLoop, Destructure,
+    DestructureEntry, Expr, Fn, Identifier, MapKey, Param, Pattern, Statement, StringFragment,
+    TraitField, TypeDef,
+};
+
+/// Mutable traversal of the syntax tree, used by the passes that rewrite the
+/// program between parsing and code generation.
+///
+/// Every method defaults to walking into the children of the node, so
+/// implementors only override the nodes they care about. Names are reported
+/// either as bindings, when declared (variables, functions, parameters,
+/// classes...), or as references, when read or assigned. Member fields, map
+/// keys and class members are neither, as they don't name variables.
+pub trait VisitorMut {
+    type Error;
+
+    fn visit_statements(&mut self, statements: &mut Vec<Statement>) -> Result<(), Self::Error> {
+        walk_statements(self, statements)
+    }
+    fn visit_statement(&mut self, statement: &mut Statement) -> Result<(), Self::Error> {
+        walk_statement(self, statement)
+    }
+    fn visit_expr(&mut self, expr: &mut Expr) -> Result<(), Self::Error> {
+        walk_expr(self, expr)
+    }
+    fn visit_target(&mut self, target: &mut AssignmentTarget) -> Result<(), Self::Error> {
+        walk_target(self, target)
+    }
+    /// Functions and methods, the name of a function statement is visited
+    /// before as a binding.
+    fn visit_fn(&mut self, fn_def: &mut Fn) -> Result<(), Self::Error> {
+        walk_fn(self, fn_def)
+    }
+    fn visit_type_def(&mut self, type_def: &mut TypeDef) -> Result<(), Self::Error> {
+        walk_type_def(self, type_def)
+    }
+    fn visit_binding(&mut self, _binding: &mut Identifier) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn visit_reference(&mut self, _reference: &mut Identifier) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+pub fn walk_statements<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    statements: &mut Vec<Statement>,
+) -> Result<(), V::Error> {
+    for statement in statements.iter_mut() {
+        visitor.visit_statement(statement)?;
+    }
+    Ok(())
+}
+
+pub fn walk_statement<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    statement: &mut Statement,
+) -> Result<(), V::Error> {
+    match statement {
+        Statement::Use(_) | Statement::Break(_) | Statement::Skip(_) | Statement::FixityDecl(_) => {
+        }
+        // Templates are only visited once expanded.
+        Statement::MacroDef(_) => (),
+        Statement::IfStatement(if_statement) => {
+            visitor.visit_expr(&mut if_statement.condition)?;
+            visitor.visit_statements(&mut if_statement.body)?;
+            for else_if in if_statement.else_if_blocks.iter_mut() {
+                visitor.visit_expr(&mut else_if.condition)?;
+                visitor.visit_statements(&mut else_if.body)?;
+            }
+            if let Some(else_block) = &mut if_statement.else_block {
+                visitor.visit_statements(else_block)?;
+            }
+        }
+        Statement::ClassDef(class) => {
+            walk_annotations(visitor, &mut class.annotations)?;
+            visitor.visit_binding(&mut class.name)?;
+            if let Some(parent) = &mut class.parent {
+                visitor.visit_reference(parent)?;
+            }
+            for trait_name in class.traits.iter_mut() {
+                visitor.visit_reference(trait_name)?;
+            }
+            for field in class.fields.iter_mut() {
+                match field {
+                    ClassField::Fn(method) => visitor.visit_fn(method)?,
+                    ClassField::Let(field) => {
+                        walk_annotations(visitor, &mut field.annotations)?;
+                        if let Some(type_def) = &mut field.type_def {
+                            visitor.visit_type_def(type_def)?;
+                        }
+                        if let Some(initializer) = &mut field.initializer {
+                            visitor.visit_expr(initializer)?;
+                        }
+                    }
+                }
+            }
+        }
+        Statement::EnumDef(enum_def) => {
+            walk_annotations(visitor, &mut enum_def.annotations)?;
+            visitor.visit_binding(&mut enum_def.name)?;
+        }
+        Statement::TraitDef(trait_def) => {
+            walk_annotations(visitor, &mut trait_def.annotations)?;
+            visitor.visit_binding(&mut trait_def.name)?;
+            for field in trait_def.fields.iter_mut() {
+                match field {
+                    TraitField::FnSignature(signature) => {
+                        walk_annotations(visitor, &mut signature.annotations)?;
+                        walk_params(visitor, &mut signature.arguments)?;
+                        if let Some(return_type) = &mut signature.return_type {
+                            visitor.visit_type_def(return_type)?;
+                        }
+                    }
+                    TraitField::Fn(method) => visitor.visit_fn(method)?,
+                }
+            }
+        }
+        Statement::Assignment(assignment) => {
+            visitor.visit_target(&mut assignment.left)?;
+            visitor.visit_expr(&mut assignment.right)?;
+        }
+        Statement::Let(let_def) => {
+            walk_annotations(visitor, &mut let_def.annotations)?;
+            if let Some(type_def) = &mut let_def.type_def {
+                visitor.visit_type_def(type_def)?;
+            }
+            if let Some(initializer) = &mut let_def.initializer {
+                visitor.visit_expr(initializer)?;
+            }
+            walk_destructure(visitor, &mut let_def.name)?;
+        }
+        Statement::Fn(fn_def) => {
+            visitor.visit_binding(&mut fn_def.name)?;
+            visitor.visit_fn(fn_def)?;
+        }
+        Statement::Loop(loop_def) => visitor.visit_statements(&mut loop_def.body)?,
+        Statement::While(while_def) => {
+            visitor.visit_expr(&mut while_def.condition)?;
+            visitor.visit_statements(&mut while_def.body)?;
+        }
+        Statement::For(for_def) => {
+            visitor.visit_expr(&mut for_def.expr)?;
+            walk_destructure(visitor, &mut for_def.assignment)?;
+            visitor.visit_statements(&mut for_def.body)?;
+        }
+        Statement::Try(try_def) => {
+            visitor.visit_statements(&mut try_def.body)?;
+            if let Some(catch) = &mut try_def.catch {
+                visitor.visit_binding(&mut catch.binding)?;
+                visitor.visit_statements(&mut catch.body)?;
+            }
+            if let Some(finally) = &mut try_def.finally {
+                visitor.visit_statements(finally)?;
+            }
+        }
+        Statement::Throw(throw) => visitor.visit_expr(&mut throw.value)?,
+        Statement::Defer(defer) => visitor.visit_statements(&mut defer.body)?,
+        Statement::Return(ret) => {
+            if let Some(value) = &mut ret.value {
+                visitor.visit_expr(value)?;
+            }
+        }
+        Statement::Expr(expr) => visitor.visit_expr(expr)?,
+    }
+    Ok(())
+}
+
+pub fn walk_expr<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) -> Result<(), V::Error> {
+    match expr {
+        Expr::Number(_) | Expr::Boolean(_) | Expr::SatString(_) => (),
+        Expr::Identifier(identifier) => visitor.visit_reference(identifier)?,
+        Expr::Call(call) => {
+            visitor.visit_expr(&mut call.target)?;
+            walk_exprs(visitor, &mut call.arguments)?;
+        }
+        Expr::ArrayAccess(access) => {
+            visitor.visit_expr(&mut access.target)?;
+            walk_exprs(visitor, &mut access.arguments)?;
+        }
+        Expr::Bop(bop) => {
+            visitor.visit_expr(&mut bop.left)?;
+            visitor.visit_expr(&mut bop.right)?;
+        }
+        Expr::Uop(uop) => visitor.visit_expr(&mut uop.expr)?,
+        Expr::LambdaExpr(lambda) => {
+            walk_params(visitor, &mut lambda.params)?;
+            if let Some(return_type) = &mut lambda.return_type {
+                visitor.visit_type_def(return_type)?;
+            }
+            visitor.visit_statements(&mut lambda.body)?;
+        }
+        Expr::InterpolatedString(string) => {
+            for fragment in string.fragments.iter_mut() {
+                if let StringFragment::Expr(expr) = fragment {
+                    visitor.visit_expr(expr)?;
+                }
+            }
+        }
+        Expr::Member(member) => visitor.visit_expr(&mut member.target)?,
+        Expr::MapLiteral(map) => {
+            for (key, value) in map.entries.iter_mut() {
+                if let MapKey::Expr(key) = key {
+                    visitor.visit_expr(key)?;
+                }
+                visitor.visit_expr(value)?;
+            }
+        }
+        Expr::ArrayLiteral(array) => walk_exprs(visitor, &mut array.values)?,
+        Expr::TupleLiteral(tuple) => walk_exprs(visitor, &mut tuple.values)?,
+        Expr::ArrayComprehension(comprehension) => {
+            walk_comprehension_loops(visitor, &mut comprehension.loops)?;
+            visitor.visit_expr(&mut comprehension.value)?;
+        }
+        Expr::MapComprehension(comprehension) => {
+            walk_comprehension_loops(visitor, &mut comprehension.loops)?;
+            visitor.visit_expr(&mut comprehension.key)?;
+            visitor.visit_expr(&mut comprehension.value)?;
+        }
+        Expr::Match(match_expr) => {
+            visitor.visit_expr(&mut match_expr.subject)?;
+            for arm in match_expr.arms.iter_mut() {
+                walk_pattern(visitor, &mut arm.pattern)?;
+                if let Some(guard) = &mut arm.guard {
+                    visitor.visit_expr(guard)?;
+                }
+                visitor.visit_expr(&mut arm.body)?;
+            }
+        }
+        Expr::IfExpr(if_expr) => {
+            visitor.visit_expr(&mut if_expr.condition)?;
+            walk_block(visitor, &mut if_expr.body)?;
+            if let Some(else_block) = &mut if_expr.else_block {
+                visitor.visit_expr(else_block)?;
+            }
+        }
+        Expr::Block(block) => walk_block(visitor, block)?,
+        Expr::Spread(spread) => visitor.visit_expr(&mut spread.expr)?,
+        Expr::Await(await_expr) => visitor.visit_expr(&mut await_expr.expr)?,
+        Expr::MacroCall(call) => walk_exprs(visitor, &mut call.arguments)?,
+    }
+    Ok(())
+}
+
+/// In source order, as each loop sees the bindings of the previous ones.
+fn walk_comprehension_loops<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    loops: &mut [ComprehensionLoop],
+) -> Result<(), V::Error> {
+    for comprehension_loop in loops.iter_mut() {
+        visitor.visit_expr(&mut comprehension_loop.expr)?;
+        walk_destructure(visitor, &mut comprehension_loop.assignment)?;
+        if let Some(condition) = &mut comprehension_loop.condition {
+            visitor.visit_expr(condition)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn walk_target<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    target: &mut AssignmentTarget,
+) -> Result<(), V::Error> {
+    match target {
+        AssignmentTarget::Identifier(identifier) => visitor.visit_reference(identifier),
+        AssignmentTarget::Member(member) => visitor.visit_expr(&mut member.target),
+        AssignmentTarget::ArrayAccess(access) => {
+            visitor.visit_expr(&mut access.target)?;
+            walk_exprs(visitor, &mut access.arguments)
+        }
+    }
+}
+
+pub fn walk_block<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    block: &mut Block,
+) -> Result<(), V::Error> {
+    visitor.visit_statements(&mut block.body)?;
+    if let Some(value) = &mut block.value {
+        visitor.visit_expr(value)?;
+    }
+    Ok(())
+}
+
+pub fn walk_pattern<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    pattern: &mut Pattern,
+) -> Result<(), V::Error> {
+    match pattern {
+        Pattern::Wildcard(_) => (),
+        Pattern::Literal(expr) => visitor.visit_expr(expr)?,
+        Pattern::Binding(identifier) => visitor.visit_binding(identifier)?,
+        Pattern::Array(items, _) | Pattern::Tuple(items, _) => {
+            for item in items.iter_mut() {
+                walk_pattern(visitor, item)?;
+            }
+        }
+        Pattern::Map(entries, _) => {
+            for (_, item) in entries.iter_mut() {
+                walk_pattern(visitor, item)?;
+            }
+        }
+        Pattern::Class(class, entries, _) => {
+            visitor.visit_reference(class)?;
+            for (_, item) in entries.iter_mut() {
+                walk_pattern(visitor, item)?;
+            }
+        }
+        Pattern::Variant(enum_name, _, items, _) => {
+            visitor.visit_reference(enum_name)?;
+            for item in items.iter_mut() {
+                walk_pattern(visitor, item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn walk_destructure<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    destructure: &mut Destructure,
+) -> Result<(), V::Error> {
+    match destructure {
+        Destructure::Identifier(identifier) => visitor.visit_binding(identifier),
+        Destructure::Array(entries) | Destructure::Tuple(entries) => {
+            walk_destructure_entries(visitor, entries, false)
+        }
+        Destructure::Map(entries) => walk_destructure_entries(visitor, entries, true),
+    }
+}
+
+/// In map destructuring, `{ name }` is both the key and the binding, so the
+/// entry is turned into `{ name: binding }` if the binding is replaced.
+fn walk_destructure_entries<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    entries: &mut [DestructureEntry],
+    is_map: bool,
+) -> Result<(), V::Error> {
+    for entry in entries.iter_mut() {
+        match entry {
+            DestructureEntry::Identifier(identifier) if is_map => {
+                let mut binding = identifier.clone();
+                visitor.visit_binding(&mut binding)?;
+                if binding.value != identifier.value {
+                    *entry = DestructureEntry::Aliasing(
+                        identifier.clone(),
+                        Box::new(DestructureEntry::Identifier(binding)),
+                    );
+                }
+            }
+            DestructureEntry::Identifier(identifier) => visitor.visit_binding(identifier)?,
+            DestructureEntry::Array(entries) | DestructureEntry::Tuple(entries) => {
+                walk_destructure_entries(visitor, entries, false)?
+            }
+            DestructureEntry::Map(entries) => walk_destructure_entries(visitor, entries, true)?,
+            DestructureEntry::Aliasing(_, entry) => {
+                walk_destructure_entries(visitor, std::slice::from_mut(entry.as_mut()), false)?
+            }
+            // Keeps the key outside, as in `{ key: binding = value }`.
+            DestructureEntry::Default(inner, value) => {
+                visitor.visit_expr(value)?;
+                walk_destructure_entries(visitor, std::slice::from_mut(inner.as_mut()), is_map)?;
+                if let DestructureEntry::Aliasing(key, aliased) = inner.as_mut() {
+                    *entry = DestructureEntry::Aliasing(
+                        key.clone(),
+                        Box::new(DestructureEntry::Default(aliased.clone(), value.clone())),
+                    );
+                }
+            }
+            DestructureEntry::Rest(identifier) => visitor.visit_binding(identifier)?,
+        }
+    }
+    Ok(())
+}
+
+pub fn walk_fn<V: VisitorMut + ?Sized>(visitor: &mut V, fn_def: &mut Fn) -> Result<(), V::Error> {
+    walk_annotations(visitor, &mut fn_def.annotations)?;
+    walk_params(visitor, &mut fn_def.arguments)?;
+    if let Some(return_type) = &mut fn_def.return_type {
+        visitor.visit_type_def(return_type)?;
+    }
+    visitor.visit_statements(&mut fn_def.body)
+}
+
+fn walk_params<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    params: &mut [Param],
+) -> Result<(), V::Error> {
+    for param in params.iter_mut() {
+        if let Some(type_def) = &mut param.type_def {
+            visitor.visit_type_def(type_def)?;
+        }
+        if let Some(init) = &mut param.init {
+            visitor.visit_expr(init)?;
+        }
+        walk_destructure(visitor, &mut param.name)?;
+    }
+    Ok(())
+}
+
+pub fn walk_type_def<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    type_def: &mut TypeDef,
+) -> Result<(), V::Error> {
+    for arg in type_def.generic_args.iter_mut().flatten() {
+        visitor.visit_type_def(arg)?;
+    }
+    Ok(())
+}
+
+fn walk_annotations<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    annotations: &mut Annotations,
+) -> Result<(), V::Error> {
+    for decorator in annotations.decorators.iter_mut() {
+        visitor.visit_expr(&mut decorator.expr)?;
+    }
+    for attribute in annotations.attributes.iter_mut() {
+        walk_exprs(visitor, &mut attribute.arguments)?;
+    }
+    Ok(())
+}
+
+fn walk_exprs<V: VisitorMut + ?Sized>(visitor: &mut V, exprs: &mut [Expr]) -> Result<(), V::Error> {
+    for expr in exprs.iter_mut() {
+        visitor.visit_expr(expr)?;
+    }
+    Ok(())
+}
@@ -27,6 +27,11 @@ pub trait SourceCode {
     fn location(&self) -> Option<PathBuf> {
         None
     }
+    /// The file the source was read from, named by the diagnostics. Defaults
+    /// to the location, which is the module path of the source.
+    fn file(&self) -> Option<PathBuf> {
+        self.location()
+    }
 }
 impl SourceCode for &'static str {
     fn source(self) -> String {
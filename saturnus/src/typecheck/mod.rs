@@ -0,0 +1,87 @@
+//! Checks over the type annotations, run before code generation.
+//!
+//! Annotations are erased from the output, so for now this only validates the
+//! generic type parameters declared by functions and classes, keeping track of
+//! the ones in scope for each annotation.
+
+use crate::{
+    diagnostics::Diagnostic,
+    parsing::ast::{Fn, Identifier, Statement, TypeDef},
+    processing::visitor::{VisitorMut, walk_fn, walk_statement, walk_type_def},
+};
+
+/// Checks the type annotations of the program.
+pub fn check_types(program: &mut Vec<Statement>) -> Result<(), Diagnostic> {
+    TypeChecker::default().visit_statements(program)
+}
+
+/// The generic type parameters in scope, innermost last. Methods see the
+/// parameters of their class, and may shadow them.
+#[derive(Debug, Default)]
+pub struct TypeScope {
+    frames: Vec<Vec<Identifier>>,
+}
+impl TypeScope {
+    pub fn enter(&mut self, params: &[Identifier]) -> Result<(), Diagnostic> {
+        for (i, param) in params.iter().enumerate() {
+            if params[..i].iter().any(|p| p.value == param.value) {
+                return Err(Diagnostic::new(
+                    format!("the type parameter `{}` is declared twice", param.value),
+                    param.span,
+                ));
+            }
+        }
+        self.frames.push(params.to_vec());
+        Ok(())
+    }
+    pub fn exit(&mut self) {
+        self.frames.pop();
+    }
+    /// The type parameter a type name refers to, if any.
+    pub fn resolve(&self, name: &str) -> Option<&Identifier> {
+        self.frames
+            .iter()
+            .rev()
+            .flatten()
+            .find(|param| param.value == name)
+    }
+}
+
+#[derive(Default)]
+struct TypeChecker {
+    scope: TypeScope,
+}
+impl VisitorMut for TypeChecker {
+    type Error = Diagnostic;
+
+    fn visit_statement(&mut self, statement: &mut Statement) -> Result<(), Diagnostic> {
+        let Statement::ClassDef(class) = statement else {
+            return walk_statement(self, statement);
+        };
+        self.scope.enter(&class.type_params)?;
+        let result = walk_statement(self, statement);
+        self.scope.exit();
+        result
+    }
+    fn visit_fn(&mut self, fn_def: &mut Fn) -> Result<(), Diagnostic> {
+        self.scope.enter(&fn_def.type_params)?;
+        let result = walk_fn(self, fn_def);
+        self.scope.exit();
+        result
+    }
+    fn visit_type_def(&mut self, type_def: &mut TypeDef) -> Result<(), Diagnostic> {
+        if let (Some(param), Some(_)) = (
+            self.scope.resolve(&type_def.name.value),
+            &type_def.generic_args,
+        ) {
+            return Err(Diagnostic::new(
+                format!(
+                    "the type parameter `{}` can't take generic arguments",
+                    param.value
+                ),
+                type_def.span,
+            ));
+        }
+        walk_type_def(self, type_def)
+    }
+}